@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Read};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use chanal_core::{Fen, Game, GameStatus, MoveGen, NodeId, Notation};
+
+const USAGE: &str = "\
+Usage: chanal-cli <command> [args]
+
+Commands:
+    fen validate [FEN...]            Check FENs, given as arguments or one per line on stdin
+    fen moves [--notation N] <FEN>   List the legal moves in a position
+    perft [--mailbox | --bench] <FEN> <depth>
+                                     Count the leaf nodes below each move; --mailbox
+                                     uses the mailbox generator, --bench times both
+    pgn convert --to N [FILE...]     Rewrite games with their moves in another notation
+    pgn validate [FILE...]           Replay every move of every game
+    pgn stats [FILE...]              Summarise the games
+
+Notations (N) are san, lan and uci. Without FILEs, or for -, PGN is read
+from stdin.";
+
+// What a command prints, to stdout and stderr, and whether everything it
+// checked was valid
+struct Output {
+    text: String,
+    errors: String,
+    success: bool,
+}
+
+impl Output {
+    fn ok(text: String) -> Self {
+        Self {
+            text,
+            errors: String::new(),
+            success: true,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(output) => {
+            print!("{}", output.text);
+            eprint!("{}", output.errors);
+            if output.success {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<Output, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["fen", "validate"] => {
+            let input = read_stdin()?;
+            let fens: Vec<_> = input.lines().filter(|l| !l.trim().is_empty()).collect();
+            Ok(fen_validate(&fens))
+        }
+        ["fen", "validate", ref fens @ ..] => Ok(fen_validate(fens)),
+        ["fen", "moves", ref args @ ..] => fen_moves(args),
+        ["perft", ref args @ ..] => perft(args),
+        ["pgn", "convert", "--to", notation, ref files @ ..] => {
+            Ok(pgn_convert(&read_inputs(files)?, parse_notation(notation)?))
+        }
+        ["pgn", "validate", ref files @ ..] => Ok(pgn_validate(&read_inputs(files)?)),
+        ["pgn", "stats", ref files @ ..] => Ok(pgn_stats(&read_inputs(files)?)),
+        ["help" | "--help" | "-h"] => Ok(Output::ok(format!("{}\n", USAGE))),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn read_stdin() -> Result<String, String> {
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|err| format!("Failed to read stdin: {}", err))?;
+    Ok(input)
+}
+
+// The named inputs to read PGN from, stdin standing in for no files or -
+fn read_inputs(files: &[&str]) -> Result<Vec<(String, String)>, String> {
+    if files.is_empty() {
+        return Ok(vec![("<stdin>".to_string(), read_stdin()?)]);
+    }
+    files
+        .iter()
+        .map(|&file| {
+            let text = if file == "-" {
+                read_stdin()?
+            } else {
+                fs::read_to_string(file)
+                    .map_err(|err| format!("Failed to read {}: {}", file, err))?
+            };
+            Ok((file.to_string(), text))
+        })
+        .collect()
+}
+
+fn parse_notation(notation: &str) -> Result<Notation, String> {
+    match notation {
+        "san" => Ok(Notation::San),
+        "lan" => Ok(Notation::Lan),
+        "uci" => Ok(Notation::Uci),
+        _ => Err(format!(
+            "Unknown notation \"{}\", expected san, lan or uci",
+            notation
+        )),
+    }
+}
+
+// The FEN may be given unquoted, as several arguments
+fn parse_fen(args: &[&str]) -> Result<Fen, String> {
+    let fen = args.join(" ");
+    fen.parse()
+        .map_err(|err| format!("Invalid FEN \"{}\": {}", fen, err))
+}
+
+fn fen_validate(fens: &[&str]) -> Output {
+    let mut output = Output::ok(String::new());
+    for fen in fens {
+        match fen.parse::<Fen>() {
+            Ok(_) => writeln!(output.text, "{}: ok", fen).unwrap(),
+            Err(err) => {
+                writeln!(output.text, "{}: {}", fen, err).unwrap();
+                output.success = false;
+            }
+        }
+    }
+    output
+}
+
+fn fen_moves(args: &[&str]) -> Result<Output, String> {
+    let (notation, args) = match args {
+        ["--notation", notation, rest @ ..] => (parse_notation(notation)?, rest),
+        _ => (Notation::San, args),
+    };
+    if args.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let fen = parse_fen(args)?;
+    let mut moves = fen.generate_moves();
+    moves.sort_by_key(|mov| mov.uci());
+    let mut text = String::new();
+    for mov in moves {
+        writeln!(text, "{}", fen.notation(mov, notation)).unwrap();
+    }
+    Ok(Output::ok(text))
+}
+
+fn perft(args: &[&str]) -> Result<Output, String> {
+    let (mode, args) = match args {
+        ["--mailbox", args @ ..] => (Some(MoveGen::Mailbox), args),
+        ["--bench", args @ ..] => (None, args),
+        _ => (Some(MoveGen::Bitboard), args),
+    };
+    let [fen @ .., depth] = args else {
+        return Err(USAGE.to_string());
+    };
+    if fen.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let depth = depth
+        .parse::<u32>()
+        .ok()
+        .filter(|&d| d > 0)
+        .ok_or_else(|| format!("Invalid depth \"{}\"", depth))?;
+    let fen = parse_fen(fen)?;
+
+    let mut text = String::new();
+    let Some(gen) = mode else {
+        let mut times = Vec::new();
+        for gen in [MoveGen::Mailbox, MoveGen::Bitboard] {
+            let start = Instant::now();
+            let nodes = fen.perft_with(depth, gen);
+            let secs = start.elapsed().as_secs_f64();
+            writeln!(
+                text,
+                "{:?}: {} nodes in {:.3}s ({:.0} nodes/s)",
+                gen,
+                nodes,
+                secs,
+                nodes as f64 / secs
+            )
+            .unwrap();
+            times.push(secs);
+        }
+        writeln!(text, "Speedup: {:.2}x", times[0] / times[1]).unwrap();
+        return Ok(Output::ok(text));
+    };
+
+    let divide = fen.divide(depth, gen);
+    for (mov, cnt) in &divide {
+        writeln!(text, "{}: {}", mov.uci(), cnt).unwrap();
+    }
+    let total: u64 = divide.iter().map(|(_, cnt)| cnt).sum();
+    writeln!(text, "\nNodes searched: {}", total).unwrap();
+    Ok(Output::ok(text))
+}
+
+// Games with errors are left out, and reported on stderr
+fn pgn_convert(inputs: &[(String, String)], notation: Notation) -> Output {
+    let mut games = Vec::new();
+    let mut output = Output::ok(String::new());
+    for (name, pgn) in inputs {
+        for (num, game) in (1..).zip(Game::all_from_pgn(pgn)) {
+            match game {
+                Ok(game) => games.push(game.to_pgn_with(notation)),
+                Err(err) => {
+                    writeln!(output.errors, "{}:{} (game {})", name, err, num).unwrap();
+                    output.success = false;
+                }
+            }
+        }
+    }
+    output.text = games.join("\n");
+    output
+}
+
+fn pgn_validate(inputs: &[(String, String)]) -> Output {
+    let mut output = Output::ok(String::new());
+    for (name, pgn) in inputs {
+        let mut cnt = 0;
+        let mut errors = Vec::new();
+        for (num, game) in (1..).zip(Game::all_from_pgn(pgn)) {
+            cnt = num;
+            match game {
+                Ok(mut game) => {
+                    if let Some(err) = result_mismatch(&mut game) {
+                        errors.push(format!("{}: game {}: {}", name, num, err));
+                    }
+                }
+                Err(err) => errors.push(format!("{}:{} (game {})", name, err, num)),
+            }
+        }
+        if errors.is_empty() {
+            writeln!(output.text, "{}: {} games ok", name, cnt).unwrap();
+        } else {
+            for err in &errors {
+                writeln!(output.text, "{}", err).unwrap();
+            }
+            let ok = cnt - errors.len();
+            writeln!(output.text, "{}: {} of {} games ok", name, ok, cnt).unwrap();
+            output.success = false;
+        }
+    }
+    output
+}
+
+// A recorded result other than the one the final position was decided by
+fn result_mismatch(game: &mut Game) -> Option<String> {
+    game.goto_end();
+    let GameStatus::Over(result, termination) = game.status() else {
+        return None;
+    };
+    let tag = game.tag("Result")?;
+    (tag != "*" && tag != result.to_string()).then(|| {
+        format!(
+            "result is {} but the game ends in {} ({})",
+            tag, termination, result
+        )
+    })
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    games: usize,
+    // Errors of the games left out
+    skipped: Vec<String>,
+    results: HashMap<String, usize>,
+    plies: Vec<usize>,
+    variations: usize,
+    comments: usize,
+    nags: usize,
+    terminations: HashMap<String, usize>,
+    first_moves: HashMap<String, usize>,
+}
+
+impl Stats {
+    fn add(&mut self, mut game: Game) {
+        self.games += 1;
+        let result = game.tag("Result").unwrap_or("*").to_string();
+        *self.results.entry(result).or_default() += 1;
+        let main_line = game.game_moves();
+        self.plies.push(main_line.len());
+        if let Some(first) = main_line.first() {
+            *self.first_moves.entry(first.notation.clone()).or_default() += 1;
+        }
+        // A freshly parsed game is at its start, where the annotation holds
+        // the comment on the game as a whole
+        if game.annotation().comment_after.is_some() {
+            self.comments += 1;
+        }
+        self.add_tree(&game, game.root());
+        game.goto_end();
+        if let GameStatus::Over(_, termination) = game.status() {
+            *self
+                .terminations
+                .entry(termination.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    fn add_tree(&mut self, game: &Game, node: NodeId) {
+        let continuations = game.continuations(node);
+        self.variations += continuations.len().saturating_sub(1);
+        for gm in continuations {
+            let annotation = &gm.annotation;
+            self.comments += [&annotation.comment_before, &annotation.comment_after]
+                .iter()
+                .filter(|c| c.is_some())
+                .count();
+            self.nags += annotation.nags.len();
+            self.add_tree(game, gm.node);
+        }
+    }
+}
+
+// Counts, most common first and ties by name
+fn ranked(counts: &HashMap<String, usize>) -> String {
+    let mut counts: Vec<_> = counts.iter().collect();
+    counts.sort_by(|(n1, c1), (n2, c2)| c2.cmp(c1).then(n1.cmp(n2)));
+    counts
+        .iter()
+        .map(|(name, cnt)| format!("{} {}", name, cnt))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn pgn_stats(inputs: &[(String, String)]) -> Output {
+    let mut stats = Stats::default();
+    for (name, pgn) in inputs {
+        for game in Game::all_from_pgn(pgn) {
+            match game {
+                Ok(game) => stats.add(game),
+                Err(err) => stats.skipped.push(format!("{}:{}", name, err)),
+            }
+        }
+    }
+
+    let mut text = String::new();
+    writeln!(text, "Games: {}", stats.games).unwrap();
+    let results = ["1-0", "0-1", "1/2-1/2", "*"]
+        .iter()
+        .map(|&r| format!("{} {}", r, stats.results.get(r).unwrap_or(&0)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(text, "Results: {}", results).unwrap();
+    let total: usize = stats.plies.iter().sum();
+    let longest = stats.plies.iter().max().unwrap_or(&0);
+    let average = total as f64 / stats.games.max(1) as f64;
+    writeln!(
+        text,
+        "Plies: {} total, {:.1} average, {} longest",
+        total, average, longest
+    )
+    .unwrap();
+    writeln!(text, "Variations: {}", stats.variations).unwrap();
+    writeln!(text, "Comments: {}", stats.comments).unwrap();
+    writeln!(text, "NAGs: {}", stats.nags).unwrap();
+    if !stats.terminations.is_empty() {
+        writeln!(text, "Ended by: {}", ranked(&stats.terminations)).unwrap();
+    }
+    if !stats.first_moves.is_empty() {
+        writeln!(text, "First moves: {}", ranked(&stats.first_moves)).unwrap();
+    }
+    if !stats.skipped.is_empty() {
+        writeln!(text, "Skipped: {} games with errors", stats.skipped.len()).unwrap();
+        for err in &stats.skipped {
+            writeln!(text, "    {}", err).unwrap();
+        }
+    }
+    Output::ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Fool's mate"]
+[Result "0-1"]
+
+1. f3 e5 2. g4 Qh4# 0-1
+
+[Event "Casual"]
+[Result "*"]
+
+{Opening} 1. e4 e5 (1... c5 $1) 2. Nf3 *
+"#;
+
+    fn inputs(pgn: &str) -> Vec<(String, String)> {
+        vec![("games.pgn".to_string(), pgn.to_string())]
+    }
+
+    #[test]
+    fn validate_fens() {
+        let output = fen_validate(&["8/8/8/8/8/8/8/K6k w - - 0 1", "8/8/8 w - - 0 1"]);
+        assert!(!output.success);
+        assert_eq!(
+            output.text,
+            "8/8/8/8/8/8/8/K6k w - - 0 1: ok\n\
+             8/8/8 w - - 0 1: Insufficient ranks in position, expected 8 got 3\n"
+        );
+    }
+
+    #[test]
+    fn perft_args() {
+        let args = [
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8",
+            "w",
+            "-",
+            "-",
+            "0",
+            "1",
+            "2",
+        ];
+        let output = perft(&args).unwrap();
+        assert!(output.text.starts_with("a5a4: 15\n"));
+        assert!(output.text.ends_with("\nNodes searched: 191\n"));
+        let mut mailbox = vec!["--mailbox"];
+        mailbox.extend(args);
+        assert_eq!(perft(&mailbox).unwrap().text, output.text);
+        let bench = perft(&["--bench", chanal_core::INIT_FEN, "2"]).unwrap();
+        assert!(bench.text.starts_with("Mailbox: 400 nodes in "));
+        assert!(bench.text.contains("\nBitboard: 400 nodes in "));
+        assert!(perft(&["2"]).is_err());
+        assert!(perft(&[chanal_core::INIT_FEN, "x"]).is_err());
+    }
+
+    #[test]
+    fn validate_games() {
+        let output = pgn_validate(&inputs(PGN));
+        assert!(output.success);
+        assert_eq!(output.text, "games.pgn: 2 games ok\n");
+
+        let wrong_result = PGN.replace("Qh4# 0-1", "Qh4# 1-0");
+        let output = pgn_validate(&inputs(&wrong_result));
+        assert!(!output.success);
+        assert_eq!(
+            output.text,
+            "games.pgn: game 1: result is 1-0 but the game ends in checkmate (0-1)\n\
+             games.pgn: 1 of 2 games ok\n"
+        );
+
+        let illegal = PGN.replace("2. Nf3", "2. Nf4");
+        let output = pgn_validate(&inputs(&illegal));
+        assert!(!output.success);
+        assert_eq!(
+            output.text,
+            "games.pgn:9:36: Illegal move: Nf4 (game 2)\n\
+             games.pgn: 1 of 2 games ok\n"
+        );
+
+        // Games after a bad one are still checked
+        let archive = format!("{}\n{}", illegal, wrong_result);
+        let output = pgn_validate(&inputs(&archive));
+        assert_eq!(
+            output.text,
+            "games.pgn:9:36: Illegal move: Nf4 (game 2)\n\
+             games.pgn: game 3: result is 1-0 but the game ends in checkmate (0-1)\n\
+             games.pgn: 2 of 4 games ok\n"
+        );
+    }
+
+    #[test]
+    fn convert_games() {
+        let output = pgn_convert(&inputs(PGN), Notation::Uci);
+        assert!(output.success);
+        assert!(output.errors.is_empty());
+        assert!(output.text.contains("\n1. f2f3 e7e5 2. g2g4 d8h4 0-1\n"));
+
+        // A bad game is reported and the games around it still converted
+        let bad = PGN.replace("2. Nf3", "2. Nf4");
+        let archive = format!("{}\n{}\n{}", PGN, bad, PGN);
+        let output = pgn_convert(&inputs(&archive), Notation::Uci);
+        assert!(!output.success);
+        assert_eq!(
+            output.errors,
+            "games.pgn:19:36: Illegal move: Nf4 (game 4)\n"
+        );
+        assert_eq!(output.text.matches("[Event ").count(), 5);
+        assert_eq!(output.text.matches("2. g1f3 *").count(), 2);
+    }
+
+    #[test]
+    fn game_stats() {
+        let output = pgn_stats(&inputs(PGN));
+        assert_eq!(
+            output.text,
+            "Games: 2\n\
+             Results: 1-0 0, 0-1 1, 1/2-1/2 0, * 1\n\
+             Plies: 7 total, 3.5 average, 4 longest\n\
+             Variations: 1\n\
+             Comments: 1\n\
+             NAGs: 1\n\
+             Ended by: checkmate 1\n\
+             First moves: e4 1, f3 1\n"
+        );
+
+        let archive = format!("{}\n{}", PGN.replace("2. Nf3", "2. Nf4"), PGN);
+        let output = pgn_stats(&inputs(&archive));
+        assert!(output.text.starts_with("Games: 3\n"));
+        assert!(output
+            .text
+            .ends_with("Skipped: 1 games with errors\n    games.pgn:9:36: Illegal move: Nf4\n"));
+    }
+}
@@ -0,0 +1,141 @@
+/// Handle to a value in an `Arena`. Slots are reused once freed, so the
+/// generation tells apart handles to the old and the new occupant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+#[derive(Debug)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> NodeId {
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.value = Some(value);
+            NodeId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            let index = self.slots.len() as u32;
+            self.slots.push(Slot {
+                generation: 0,
+                value: Some(value),
+            });
+            NodeId {
+                index,
+                generation: 0,
+            }
+        }
+    }
+
+    pub fn try_get(&self, id: NodeId) -> Option<&T> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn try_get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+
+    pub fn get(&self, id: NodeId) -> &T {
+        self.try_get(id).expect("stale node id")
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> &mut T {
+        self.try_get_mut(id).expect("stale node id")
+    }
+
+    pub fn delete(&mut self, id: NodeId) -> Option<T> {
+        let slot = self
+            .slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)?;
+        let value = slot.value.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+        Some(value)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_ids() {
+        let mut arena = Arena::new();
+        let a = arena.insert("a");
+        let b = arena.insert("b");
+        assert_eq!(arena.delete(a), Some("a"));
+        assert_eq!(arena.delete(a), None);
+        assert_eq!(arena.try_get(a), None);
+
+        // The freed slot is reused, but the old id does not see the new value
+        let c = arena.insert("c");
+        assert_eq!(c.index, a.index);
+        assert_eq!(arena.try_get(a), None);
+        assert_eq!(arena.try_get_mut(a), None);
+        assert_eq!(arena.get(c), &"c");
+        assert_eq!(arena.get(b), &"b");
+        assert_eq!(arena.len(), 2);
+    }
+
+    #[test]
+    fn churn() {
+        let mut arena = Arena::new();
+        let mut live: Vec<(NodeId, usize)> = Vec::new();
+        let mut dead = Vec::new();
+        for round in 0..50 {
+            for i in 0..20 {
+                let value = round * 100 + i;
+                live.push((arena.insert(value), value));
+            }
+            // Free every third value, from varying positions
+            let mut i = round % 3;
+            while i < live.len() {
+                let (id, value) = live.remove(i);
+                assert_eq!(arena.delete(id), Some(value));
+                dead.push(id);
+                i += 2;
+            }
+            for &(id, value) in &live {
+                assert_eq!(arena.get(id), &value);
+            }
+            for &id in &dead {
+                assert_eq!(arena.try_get(id), None);
+            }
+            assert_eq!(arena.len(), live.len());
+        }
+        // Slots are reused rather than the arena growing with every insert
+        assert!(arena.slots.len() < 100);
+    }
+}
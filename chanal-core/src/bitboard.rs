@@ -0,0 +1,448 @@
+use enum_iterator::{all, cardinality};
+
+use super::{Board, CapturedPiece, Colour, Fen, Move, Piece, Position, BOARD_SIZE};
+
+// One bit per square, square index being rank * 8 + file
+type Bitboard = u64;
+
+const SQUARE_CNT: usize = BOARD_SIZE * BOARD_SIZE;
+const PIECE_CNT: usize = cardinality::<Piece>();
+
+// Rook directions first, then bishop directions, as (rank, file) steps
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+    (1, 1),
+];
+
+const KNIGHT_STEPS: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (2, -1),
+    (2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+];
+
+const KING_STEPS: [(isize, isize); 8] = DIRECTIONS;
+
+const PROMOTIONS: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+struct Tables {
+    knight: [Bitboard; SQUARE_CNT],
+    king: [Bitboard; SQUARE_CNT],
+    // Squares attacked by a pawn of either colour
+    pawn: [[Bitboard; SQUARE_CNT]; 2],
+    // Squares up to the edge of the board in each of DIRECTIONS
+    rays: [[Bitboard; SQUARE_CNT]; DIRECTIONS.len()],
+}
+
+const fn offset(sq: usize, (dr, df): (isize, isize)) -> Option<usize> {
+    let rank = (sq / BOARD_SIZE) as isize + dr;
+    let file = (sq % BOARD_SIZE) as isize + df;
+    if rank < 0 || rank >= BOARD_SIZE as isize || file < 0 || file >= BOARD_SIZE as isize {
+        None
+    } else {
+        Some(rank as usize * BOARD_SIZE + file as usize)
+    }
+}
+
+const fn leaper_attacks(sq: usize, steps: &[(isize, isize)]) -> Bitboard {
+    let mut attacks = 0;
+    let mut i = 0;
+    while i < steps.len() {
+        if let Some(to) = offset(sq, steps[i]) {
+            attacks |= 1 << to;
+        }
+        i += 1;
+    }
+    attacks
+}
+
+const fn ray(sq: usize, dir: (isize, isize)) -> Bitboard {
+    let mut attacks = 0;
+    let mut curr = sq;
+    while let Some(to) = offset(curr, dir) {
+        attacks |= 1 << to;
+        curr = to;
+    }
+    attacks
+}
+
+const fn generate_tables() -> Tables {
+    let mut tables = Tables {
+        knight: [0; SQUARE_CNT],
+        king: [0; SQUARE_CNT],
+        pawn: [[0; SQUARE_CNT]; 2],
+        rays: [[0; SQUARE_CNT]; DIRECTIONS.len()],
+    };
+    let mut sq = 0;
+    while sq < SQUARE_CNT {
+        tables.knight[sq] = leaper_attacks(sq, &KNIGHT_STEPS);
+        tables.king[sq] = leaper_attacks(sq, &KING_STEPS);
+        tables.pawn[Colour::White as usize][sq] = leaper_attacks(sq, &[(-1, -1), (-1, 1)]);
+        tables.pawn[Colour::Black as usize][sq] = leaper_attacks(sq, &[(1, -1), (1, 1)]);
+        let mut dir = 0;
+        while dir < DIRECTIONS.len() {
+            tables.rays[dir][sq] = ray(sq, DIRECTIONS[dir]);
+            dir += 1;
+        }
+        sq += 1;
+    }
+    tables
+}
+
+static TABLES: Tables = generate_tables();
+
+// Classical ray attacks: the ray stops at the nearest blocker, which is the
+// lowest set bit for directions going up the board index and the highest
+// one otherwise
+fn ray_attacks(sq: usize, dir: usize, occupied: Bitboard) -> Bitboard {
+    let ray = TABLES.rays[dir][sq];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    let (dr, df) = DIRECTIONS[dir];
+    let nearest = if dr * BOARD_SIZE as isize + df > 0 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ TABLES.rays[dir][nearest as usize]
+}
+
+fn rook_attacks(sq: usize, occupied: Bitboard) -> Bitboard {
+    (0..4).fold(0, |attacks, dir| attacks | ray_attacks(sq, dir, occupied))
+}
+
+fn bishop_attacks(sq: usize, occupied: Bitboard) -> Bitboard {
+    (4..8).fold(0, |attacks, dir| attacks | ray_attacks(sq, dir, occupied))
+}
+
+fn index((rank, file): (usize, usize)) -> usize {
+    rank * BOARD_SIZE + file
+}
+
+fn square(sq: usize) -> (usize, usize) {
+    (sq / BOARD_SIZE, sq % BOARD_SIZE)
+}
+
+// Iterates over the set squares of a bitboard
+struct Squares(Bitboard);
+
+impl Iterator for Squares {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let sq = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(sq)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bitboards {
+    pieces: [[Bitboard; PIECE_CNT]; 2],
+    colours: [Bitboard; 2],
+}
+
+impl Bitboards {
+    fn from_board(board: &Board) -> Self {
+        let mut bbs = Bitboards {
+            pieces: [[0; PIECE_CNT]; 2],
+            colours: [0; 2],
+        };
+        for sq in 0..SQUARE_CNT {
+            let (rank, file) = square(sq);
+            if let Position::Occupied(piece, colour) = board[rank][file] {
+                bbs.toggle(piece, colour, sq);
+            }
+        }
+        bbs
+    }
+
+    fn toggle(&mut self, piece: Piece, colour: Colour, sq: usize) {
+        self.pieces[colour as usize][piece as usize] ^= 1 << sq;
+        self.colours[colour as usize] ^= 1 << sq;
+    }
+
+    fn get(&self, piece: Piece, colour: Colour) -> Bitboard {
+        self.pieces[colour as usize][piece as usize]
+    }
+
+    fn occupied(&self) -> Bitboard {
+        self.colours[0] | self.colours[1]
+    }
+
+    fn piece_at(&self, sq: usize, colour: Colour) -> Option<Piece> {
+        all::<Piece>().find(|&piece| self.get(piece, colour) & (1 << sq) != 0)
+    }
+
+    fn attackers(&self, sq: usize, attacker: Colour) -> Bitboard {
+        let occupied = self.occupied();
+        let queens = self.get(Piece::Queen, attacker);
+        (TABLES.knight[sq] & self.get(Piece::Knight, attacker))
+            | (TABLES.king[sq] & self.get(Piece::King, attacker))
+            | (TABLES.pawn[attacker.opposite() as usize][sq] & self.get(Piece::Pawn, attacker))
+            | (rook_attacks(sq, occupied) & (self.get(Piece::Rook, attacker) | queens))
+            | (bishop_attacks(sq, occupied) & (self.get(Piece::Bishop, attacker) | queens))
+    }
+
+    fn is_attacked(&self, sq: usize, attacker: Colour) -> bool {
+        self.attackers(sq, attacker) != 0
+    }
+
+    // Number of pieces giving check to the king of `colour`, none if there
+    // is no such king
+    fn check_cnt(&self, colour: Colour) -> usize {
+        Squares(self.get(Piece::King, colour))
+            .next()
+            .map_or(0, |sq| {
+                self.attackers(sq, colour.opposite()).count_ones() as usize
+            })
+    }
+
+    fn apply_move(&self, mov: &Move) -> Self {
+        let mut bbs = *self;
+        if let Some(cap) = mov.capture {
+            bbs.toggle(cap.piece, cap.colour, index(cap.pos));
+        }
+        bbs.toggle(mov.piece, mov.colour, index(mov.from));
+        let (piece, colour) = mov.promotion.unwrap_or((mov.piece, mov.colour));
+        bbs.toggle(piece, colour, index(mov.to));
+        let rank = mov.from.0;
+        if mov.is_king_castle() {
+            bbs.toggle(Piece::Rook, mov.colour, index((rank, 7)));
+            bbs.toggle(Piece::Rook, mov.colour, index((rank, 5)));
+        } else if mov.is_queen_castle() {
+            bbs.toggle(Piece::Rook, mov.colour, index((rank, 0)));
+            bbs.toggle(Piece::Rook, mov.colour, index((rank, 3)));
+        }
+        bbs
+    }
+
+    // Keeps the move if it does not leave the mover's king in check
+    fn push_legal(&self, moves: &mut Vec<Move>, mut mov: Move) {
+        let after = self.apply_move(&mov);
+        if after.check_cnt(mov.colour) == 0 {
+            mov.check_cnt = after.check_cnt(mov.colour.opposite());
+            moves.push(mov);
+        }
+    }
+}
+
+impl Fen {
+    pub(super) fn generate_moves_bitboard(&self) -> Vec<Move> {
+        let bbs = Bitboards::from_board(&self.board);
+        let us = self.to_move;
+        let them = us.opposite();
+        let own = bbs.colours[us as usize];
+        let occupied = bbs.occupied();
+        let mut moves = Vec::with_capacity(64);
+
+        for piece in all::<Piece>() {
+            for from in Squares(bbs.get(piece, us)) {
+                let targets = match piece {
+                    Piece::Pawn => {
+                        self.pawn_moves(&bbs, from, &mut moves);
+                        continue;
+                    }
+                    Piece::Knight => TABLES.knight[from],
+                    Piece::King => TABLES.king[from],
+                    Piece::Rook => rook_attacks(from, occupied),
+                    Piece::Bishop => bishop_attacks(from, occupied),
+                    Piece::Queen => rook_attacks(from, occupied) | bishop_attacks(from, occupied),
+                };
+                for to in Squares(targets & !own) {
+                    let mov = Move {
+                        piece,
+                        colour: us,
+                        from: square(from),
+                        to: square(to),
+                        capture: bbs.piece_at(to, them).map(|cap| CapturedPiece {
+                            piece: cap,
+                            colour: them,
+                            pos: square(to),
+                        }),
+                        check_cnt: 0,
+                        may_promote: false,
+                        promotion: None,
+                    };
+                    bbs.push_legal(&mut moves, mov);
+                }
+            }
+        }
+        self.castling_moves(&bbs, &mut moves);
+        moves
+    }
+
+    fn pawn_moves(&self, bbs: &Bitboards, from: usize, moves: &mut Vec<Move>) {
+        let us = self.to_move;
+        let them = us.opposite();
+        let empty = !bbs.occupied();
+        let (start_rank, last_rank) = match us {
+            Colour::White => (6, 0),
+            Colour::Black => (1, 7),
+        };
+        let forward = |bb: Bitboard| match us {
+            Colour::White => bb >> BOARD_SIZE,
+            Colour::Black => bb << BOARD_SIZE,
+        };
+
+        let single = forward(1 << from) & empty;
+        let mut targets = single;
+        if square(from).0 == start_rank {
+            targets |= forward(single) & empty;
+        }
+        targets |= TABLES.pawn[us as usize][from] & bbs.colours[them as usize];
+
+        for to in Squares(targets) {
+            let mov = Move {
+                piece: Piece::Pawn,
+                colour: us,
+                from: square(from),
+                to: square(to),
+                capture: bbs.piece_at(to, them).map(|cap| CapturedPiece {
+                    piece: cap,
+                    colour: them,
+                    pos: square(to),
+                }),
+                check_cnt: 0,
+                may_promote: false,
+                promotion: None,
+            };
+            if square(to).0 == last_rank {
+                for promotion in PROMOTIONS {
+                    let mut mov = mov;
+                    mov.may_promote = true;
+                    mov.promotion = Some((promotion, us));
+                    bbs.push_legal(moves, mov);
+                }
+            } else {
+                bbs.push_legal(moves, mov);
+            }
+        }
+
+        if let Some(ep) = self.en_passant {
+            if TABLES.pawn[us as usize][from] & (1 << index(ep)) != 0 {
+                let mov = Move {
+                    piece: Piece::Pawn,
+                    colour: us,
+                    from: square(from),
+                    to: ep,
+                    capture: Some(CapturedPiece {
+                        piece: Piece::Pawn,
+                        colour: them,
+                        pos: (square(from).0, ep.1),
+                    }),
+                    check_cnt: 0,
+                    may_promote: false,
+                    promotion: None,
+                };
+                bbs.push_legal(moves, mov);
+            }
+        }
+    }
+
+    fn castling_moves(&self, bbs: &Bitboards, moves: &mut Vec<Move>) {
+        let us = self.to_move;
+        let them = us.opposite();
+        let (home, king_castle, queen_castle) = match us {
+            Colour::White => (7, self.white_king_castle, self.white_queen_castle),
+            Colour::Black => (0, self.black_king_castle, self.black_queen_castle),
+        };
+        let king = index((home, 4));
+        // Cannot castle out of check
+        if bbs.get(Piece::King, us) & (1 << king) == 0 || bbs.is_attacked(king, them) {
+            return;
+        }
+        let occupied = bbs.occupied();
+        let rooks = bbs.get(Piece::Rook, us);
+        let is_rook = |f: usize| rooks & (1 << index((home, f))) != 0;
+        let is_empty = |f: usize| occupied & (1 << index((home, f))) == 0;
+        let is_safe = |f: usize| !bbs.is_attacked(index((home, f)), them);
+        let castle = |file: usize| Move {
+            piece: Piece::King,
+            colour: us,
+            from: (home, 4),
+            to: (home, file),
+            capture: None,
+            check_cnt: 0,
+            may_promote: false,
+            promotion: None,
+        };
+        // The king may not pass through or land on an attacked square
+        if king_castle && is_rook(7) && is_empty(5) && is_empty(6) && is_safe(5) && is_safe(6) {
+            bbs.push_legal(moves, castle(6));
+        }
+        if queen_castle
+            && is_rook(0)
+            && is_empty(1)
+            && is_empty(2)
+            && is_empty(3)
+            && is_safe(3)
+            && is_safe(2)
+        {
+            bbs.push_legal(moves, castle(2));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MoveGen, INIT_FEN};
+    use super::*;
+
+    #[test]
+    fn slider_attacks() {
+        // Rook on d4 with blockers on d6 and f4
+        let d4 = index((4, 3));
+        let occupied = (1 << index((2, 3))) | (1 << index((4, 5)));
+        let attacks = rook_attacks(d4, occupied);
+        assert_eq!(attacks.count_ones(), 3 + 2 + 2 + 3);
+        assert_ne!(attacks & (1 << index((2, 3))), 0);
+        assert_eq!(attacks & (1 << index((1, 3))), 0);
+        assert_ne!(attacks & (1 << index((4, 5))), 0);
+        assert_eq!(attacks & (1 << index((4, 6))), 0);
+        // An empty board corner bishop sees the whole long diagonal
+        assert_eq!(bishop_attacks(index((7, 0)), 0).count_ones(), 7);
+    }
+
+    // Both generators must agree on every node of the tree
+    fn cross_check(fen: &Fen, depth: u32) {
+        let mut bitboard = fen.generate_moves_with(MoveGen::Bitboard);
+        let mut mailbox = fen.generate_moves_with(MoveGen::Mailbox);
+        bitboard.sort();
+        mailbox.sort();
+        assert_eq!(bitboard, mailbox, "moves of {}", fen);
+        if depth > 1 {
+            for mov in bitboard {
+                cross_check(&fen.apply_move(mov), depth - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_mailbox() {
+        for fen in [
+            INIT_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            cross_check(&fen.parse().unwrap(), 3);
+        }
+    }
+}
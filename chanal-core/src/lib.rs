@@ -0,0 +1,3128 @@
+//! Chess rules and game records for chanal, without any GUI.
+//!
+//! A [`Game`] is a tree of moves from a starting position, with variations,
+//! annotations and PGN import and export. Positions are [`Fen`]s, and the
+//! legal [`Move`]s in one come from [`Fen::generate_moves`] or, for a game,
+//! [`Game::all_legal_moves`]. Squares are `(rank, file)` pairs indexing into
+//! [`Board`], with rank 0 being the eighth rank.
+//!
+//! Positions can be evaluated by an external UCI [`Engine`], or by the
+//! built-in [`search`], which a [`Computer`] runs on a thread of its own.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+
+use enum_iterator::Sequence;
+use itertools::iproduct;
+
+use arena::Arena;
+pub use arena::NodeId;
+pub use pgn::{PgnError, PgnErrorKind};
+pub use search::{search, Computer, Limits, SearchResult};
+pub use uci::{Engine, EngineConfig, EngineError, EvalSource, Info, Score};
+
+mod arena;
+mod bitboard;
+mod perft;
+mod pgn;
+mod search;
+mod uci;
+mod zobrist;
+
+/// A kind of chess piece, regardless of colour.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+pub enum Piece {
+    Pawn,
+    Rook,
+    Knight,
+    Bishop,
+    King,
+    Queen,
+}
+
+impl Piece {
+    fn to_char(self) -> Option<char> {
+        match self {
+            Piece::Pawn => None,
+            Piece::Rook => Some('R'),
+            Piece::Knight => Some('N'),
+            Piece::Bishop => Some('B'),
+            Piece::King => Some('K'),
+            Piece::Queen => Some('Q'),
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            'R' => Some(Piece::Rook),
+            'N' => Some(Piece::Knight),
+            'B' => Some(Piece::Bishop),
+            'K' => Some(Piece::King),
+            'Q' => Some(Piece::Queen),
+            _ => None,
+        }
+    }
+}
+
+/// The side a piece belongs to.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Sequence)]
+pub enum Colour {
+    White,
+    Black,
+}
+
+impl Colour {
+    const fn opposite(self) -> Self {
+        match self {
+            Colour::White => Colour::Black,
+            Colour::Black => Colour::White,
+        }
+    }
+}
+
+/// The contents of a square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Empty,
+    Occupied(Piece, Colour),
+}
+
+/// Number of ranks, and of files, on the board.
+pub const BOARD_SIZE: usize = 8;
+
+/// The standard starting position.
+pub const INIT_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Squares indexed as `[rank][file]`, where rank 0 is the eighth rank and
+/// file 0 is the a-file. Squares are passed around as `(rank, file)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Board(pub [[Position; BOARD_SIZE]; BOARD_SIZE]);
+
+impl Deref for Board {
+    type Target = [[Position; BOARD_SIZE]; BOARD_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Board {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[derive(Debug, Default)]
+struct KingCheckCnt {
+    white: usize,
+    black: usize,
+}
+
+impl KingCheckCnt {
+    fn check_cnt(&self, colour: Colour) -> usize {
+        match colour {
+            Colour::White => self.white,
+            Colour::Black => self.black,
+        }
+    }
+}
+
+fn rank_to_char(rank: usize) -> char {
+    ((BOARD_SIZE - rank) as u8 + b'0') as char
+}
+
+fn file_to_char(file: usize) -> char {
+    (b'a' + file as u8) as char
+}
+
+impl Board {
+    fn apply_move(&mut self, mov: Move) {
+        let (fr, ff) = mov.from;
+        let (tr, tf) = mov.to;
+        self[fr][ff] = Position::Empty;
+        if let Some(cap) = mov.capture {
+            let (cr, cf) = cap.pos;
+            self[cr][cf] = Position::Empty;
+        }
+        let (piece, colour) = if let Some((piece, colour)) = mov.promotion {
+            (piece, colour)
+        } else {
+            (mov.piece, mov.colour)
+        };
+        self[tr][tf] = Position::Occupied(piece, colour);
+        if mov.piece == Piece::King {
+            if mov.colour == Colour::White {
+                if mov.from == (7, 4) && mov.to == (7, 6) {
+                    self[7][7] = Position::Empty;
+                    self[7][5] = Position::Occupied(Piece::Rook, Colour::White);
+                } else if mov.from == (7, 4) && mov.to == (7, 2) {
+                    self[7][0] = Position::Empty;
+                    self[7][3] = Position::Occupied(Piece::Rook, Colour::White);
+                }
+            } else if mov.from == (0, 4) && mov.to == (0, 6) {
+                self[0][7] = Position::Empty;
+                self[0][5] = Position::Occupied(Piece::Rook, Colour::Black);
+            } else if mov.from == (0, 4) && mov.to == (0, 2) {
+                self[0][0] = Position::Empty;
+                self[0][3] = Position::Occupied(Piece::Rook, Colour::Black);
+            }
+        }
+    }
+
+    fn unapply_move(&mut self, mov: Move) {
+        let (fr, ff) = mov.from;
+        let (tr, tf) = mov.to;
+        self[tr][tf] = Position::Empty;
+        if let Some(cap) = mov.capture {
+            let (cr, cf) = cap.pos;
+            self[cr][cf] = Position::Occupied(cap.piece, cap.colour);
+        }
+        self[fr][ff] = Position::Occupied(mov.piece, mov.colour);
+        if mov.piece == Piece::King {
+            if mov.colour == Colour::White {
+                if mov.from == (7, 4) && mov.to == (7, 6) {
+                    self[7][5] = Position::Empty;
+                    self[7][7] = Position::Occupied(Piece::Rook, Colour::White);
+                } else if mov.from == (7, 4) && mov.to == (7, 2) {
+                    self[7][3] = Position::Empty;
+                    self[7][0] = Position::Occupied(Piece::Rook, Colour::White);
+                }
+            } else if mov.from == (0, 4) && mov.to == (0, 6) {
+                self[0][5] = Position::Empty;
+                self[0][7] = Position::Occupied(Piece::Rook, Colour::Black);
+            } else if mov.from == (0, 4) && mov.to == (0, 2) {
+                self[0][3] = Position::Empty;
+                self[0][0] = Position::Occupied(Piece::Rook, Colour::Black);
+            }
+        }
+    }
+
+    fn move_verify_checks(&self, mov: &mut Move) -> bool {
+        let mut nb = *self;
+        nb.apply_move(*mov);
+        let kic = nb.king_check_cnt();
+        if kic.check_cnt(mov.colour) != 0 {
+            false
+        } else {
+            mov.check_cnt = kic.check_cnt(mov.colour.opposite());
+            true
+        }
+    }
+
+    fn king_position(&self, colour: Colour) -> (usize, usize) {
+        for rank in 0..BOARD_SIZE {
+            for file in 0..BOARD_SIZE {
+                if matches!(self[rank][file], Position::Occupied(Piece::King, kc) if kc == colour) {
+                    return (rank, file);
+                }
+            }
+        }
+        let colstr = match colour {
+            Colour::White => "white",
+            Colour::Black => "black",
+        };
+        unreachable!(
+            "There must be {} king of either colour on the board",
+            colstr
+        );
+    }
+
+    fn make_move(
+        &self,
+        piece: Piece,
+        colour: Colour,
+        rank: usize,
+        file: usize,
+        new_rank: usize,
+        new_file: usize,
+    ) -> Option<Move> {
+        let pos = self[new_rank][new_file];
+        let mut mov = match pos {
+            Position::Empty => Move {
+                piece,
+                colour,
+                from: (rank, file),
+                to: (new_rank, new_file),
+                capture: None,
+                check_cnt: 0,
+                may_promote: false,
+                promotion: None,
+            },
+            Position::Occupied(np, nc) => {
+                if nc == colour {
+                    return None;
+                } else {
+                    Move {
+                        piece,
+                        colour,
+                        from: (rank, file),
+                        to: (new_rank, new_file),
+                        capture: Some(CapturedPiece {
+                            piece: np,
+                            colour: nc,
+                            pos: (new_rank, new_file),
+                        }),
+                        check_cnt: 0,
+                        may_promote: false,
+                        promotion: None,
+                    }
+                }
+            }
+        };
+        if self.move_verify_checks(&mut mov) {
+            Some(mov)
+        } else {
+            None
+        }
+    }
+
+    fn king_check_cnt(&self) -> KingCheckCnt {
+        let mut kic = KingCheckCnt::default();
+        for rank in 0..BOARD_SIZE {
+            for file in 0..BOARD_SIZE {
+                let pos = self[rank][file];
+                if let Position::Occupied(Piece::King, Colour::White) = pos {
+                    kic.white = self.attack_cnt(rank, file, Colour::Black);
+                } else if let Position::Occupied(Piece::King, Colour::Black) = pos {
+                    kic.black = self.attack_cnt(rank, file, Colour::White);
+                }
+            }
+        }
+        kic
+    }
+
+    // Neither side can mate with a lone minor piece, or with any number of
+    // bishops that all stand on one square colour
+    fn has_insufficient_material(&self) -> bool {
+        // Minor pieces along with the square colour they stand on
+        let mut minors = Vec::new();
+        for (rank, file) in iproduct!(0..BOARD_SIZE, 0..BOARD_SIZE) {
+            match self[rank][file] {
+                Position::Empty | Position::Occupied(Piece::King, _) => {}
+                Position::Occupied(piece @ (Piece::Knight | Piece::Bishop), _) => {
+                    minors.push((piece, (rank + file) % 2));
+                }
+                _ => return false,
+            }
+        }
+        match minors[..] {
+            [] | [_] => true,
+            [(_, sq), ..] => minors.iter().all(|&minor| minor == (Piece::Bishop, sq)),
+        }
+    }
+
+    /// Whether any piece of `attacker` attacks the square.
+    pub fn is_attacked(&self, rank: usize, file: usize, attacker: Colour) -> bool {
+        self.attack_cnt(rank, file, attacker) > 0
+    }
+
+    /// Number of pieces of `attacker` attacking the square.
+    pub fn attack_cnt(&self, rank: usize, file: usize, attacker: Colour) -> usize {
+        let mut check_cnt = 0;
+        // Find knight attacks
+        for (nr, nf) in knight_distance_positions(rank, file) {
+            let pos = self[nr][nf];
+            if matches!(pos, Position::Occupied(Piece::Knight, col) if col == attacker) {
+                check_cnt += 1;
+            }
+        }
+        // Find file attacks (from rook and queen)
+        for nr in (0..rank).rev() {
+            let pos = self[nr][file];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Rook || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        for nr in rank + 1..BOARD_SIZE {
+            let pos = self[nr][file];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Rook || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        // Find rank attacks (from rook and queen)
+        for nf in (0..file).rev() {
+            let pos = self[rank][nf];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Rook || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        for nf in file + 1..BOARD_SIZE {
+            let pos = self[rank][nf];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Rook || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        // Find diagonal attacks (from queen and bishop)
+        for diff in 1..=rank.min(file) {
+            let nr = rank - diff;
+            let nf = file - diff;
+            let pos = self[nr][nf];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Bishop || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        for diff in 1..(BOARD_SIZE - rank).min(BOARD_SIZE - file) {
+            let nr = rank + diff;
+            let nf = file + diff;
+            let pos = self[nr][nf];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Bishop || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        for diff in 1..(rank + 1).min(BOARD_SIZE - file) {
+            let nr = rank - diff;
+            let nf = file + diff;
+            let pos = self[nr][nf];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Bishop || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        for diff in 1..(BOARD_SIZE - rank).min(file + 1) {
+            let nr = rank + diff;
+            let nf = file - diff;
+            let pos = self[nr][nf];
+            match pos {
+                Position::Empty => continue,
+                Position::Occupied(np, nc) => {
+                    if nc == attacker && (np == Piece::Bishop || np == Piece::Queen) {
+                        check_cnt += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        // Find pawn attacks
+        for (nr, nf) in attacking_pawn_positions(rank, file, attacker.opposite()) {
+            let pos = self[nr][nf];
+            if matches!(pos, Position::Occupied(Piece::Pawn, col) if col == attacker) {
+                check_cnt += 1;
+            }
+        }
+        // Find king attacks
+        for (nr, nf) in king_distance_positions(rank, file) {
+            let pos = self[nr][nf];
+            if matches!(pos, Position::Occupied(Piece::King, col) if col == attacker) {
+                check_cnt += 1;
+            }
+        }
+        check_cnt
+    }
+}
+
+fn diff_positions(
+    rank: usize,
+    file: usize,
+    rank_diff: &[isize],
+    file_diff: &[isize],
+) -> Vec<(usize, usize)> {
+    rank_diff
+        .iter()
+        .zip(file_diff)
+        .filter_map(|(rd, fd)| {
+            let new_rank = rank as isize + *rd;
+            let new_file = file as isize + *fd;
+            if new_rank < 0
+                || new_rank >= BOARD_SIZE as isize
+                || new_file < 0
+                || new_file >= BOARD_SIZE as isize
+            {
+                None
+            } else {
+                Some((new_rank as usize, new_file as usize))
+            }
+        })
+        .collect()
+}
+
+fn attacking_pawn_positions(rank: usize, file: usize, colour: Colour) -> Vec<(usize, usize)> {
+    let (rank_diff, file_diff) = if colour == Colour::Black {
+        ([1, 1], [-1, 1])
+    } else {
+        ([-1, -1], [-1, 1])
+    };
+    diff_positions(rank, file, &rank_diff, &file_diff)
+}
+
+fn knight_distance_positions(rank: usize, file: usize) -> Vec<(usize, usize)> {
+    const RANK_DIFF: [isize; 8] = [-2, -2, 2, 2, -1, -1, 1, 1];
+    const FILE_DIFF: [isize; 8] = [-1, 1, -1, 1, -2, 2, -2, 2];
+    diff_positions(rank, file, &RANK_DIFF, &FILE_DIFF)
+}
+
+fn king_distance_positions(rank: usize, file: usize) -> Vec<(usize, usize)> {
+    const RANK_DIFF: [isize; 8] = [-1, 0, 1, -1, 1, -1, 0, 1];
+    const FILE_DIFF: [isize; 8] = [-1, -1, -1, 0, 0, 1, 1, 1];
+    diff_positions(rank, file, &RANK_DIFF, &FILE_DIFF)
+}
+
+#[derive(Debug)]
+struct FenNode {
+    fen: Fen,
+    is_check: bool,
+    status: GameStatus,
+    parent: Option<NodeId>,
+    children: Vec<(Move, NodeId)>,
+    next_child: Option<(Move, NodeId)>,
+    annotation: Annotation,
+}
+
+impl FenNode {
+    fn root(fen: Fen, is_check: bool, status: GameStatus) -> Self {
+        Self {
+            fen,
+            parent: None,
+            children: Vec::new(),
+            is_check,
+            status,
+            next_child: None,
+            annotation: Annotation::default(),
+        }
+    }
+
+    fn internal_node(fen: Fen, is_check: bool, status: GameStatus, parent: NodeId) -> Self {
+        Self {
+            fen,
+            parent: Some(parent),
+            children: Vec::new(),
+            is_check,
+            status,
+            next_child: None,
+            annotation: Annotation::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FenTree {
+    store: Arena<FenNode>,
+    root: NodeId,
+    curr: NodeId,
+    // Nodes by the Zobrist hash of their position, to find transpositions
+    positions: HashMap<u64, Vec<NodeId>>,
+}
+
+/// A move in the game tree, along with what is needed to display it.
+#[derive(Debug)]
+pub struct GameMove {
+    /// The move itself.
+    pub mov: Move,
+    /// Node reached by playing the move
+    pub node: NodeId,
+    /// Whether the move leads to the current position.
+    pub is_curr: bool,
+    /// Full move number the move is played on.
+    pub move_num: u32,
+    /// Standard algebraic notation, such as `Nxe5+`.
+    pub notation: String,
+    /// Comments and NAGs attached to the move.
+    pub annotation: Annotation,
+}
+
+/// Numeric Annotation Glyph, as used in PGN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Nag(pub u8);
+
+const NAG_GLYPHS: [(u8, &str); 14] = [
+    (1, "!"),
+    (2, "?"),
+    (3, "!!"),
+    (4, "??"),
+    (5, "!?"),
+    (6, "?!"),
+    (10, "="),
+    (13, "~"),
+    (14, "+="),
+    (15, "=+"),
+    (16, "+/-"),
+    (17, "-/+"),
+    (18, "+-"),
+    (19, "-+"),
+];
+
+impl Nag {
+    /// The NAG written as `glyph`, such as `!?`.
+    pub fn from_glyph(glyph: &str) -> Option<Self> {
+        NAG_GLYPHS
+            .iter()
+            .find(|(_, g)| *g == glyph)
+            .map(|&(nag, _)| Self(nag))
+    }
+
+    /// The symbol for the NAG, if it has one.
+    pub fn glyph(self) -> Option<&'static str> {
+        NAG_GLYPHS
+            .iter()
+            .find(|(nag, _)| *nag == self.0)
+            .map(|(_, glyph)| *glyph)
+    }
+
+    /// Whether this judges the move itself (!, ?, ...) rather than the position
+    pub fn is_move_assessment(self) -> bool {
+        (1..=6).contains(&self.0)
+    }
+}
+
+impl fmt::Display for Nag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.glyph() {
+            Some(glyph) => write!(f, "{}", glyph),
+            None => write!(f, "${}", self.0),
+        }
+    }
+}
+
+/// Comments and NAGs attached to a move.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    /// Comment written before the move, which starts a variation.
+    pub comment_before: Option<String>,
+    /// Comment written after the move.
+    pub comment_after: Option<String>,
+    /// NAGs, kept sorted.
+    pub nags: Vec<Nag>,
+}
+
+impl Annotation {
+    /// Adds the NAG where it sorts, unless the move already has it.
+    pub fn add_nag(&mut self, nag: Nag) {
+        if let Err(i) = self.nags.binary_search(&nag) {
+            self.nags.insert(i, nag);
+        }
+    }
+
+    /// A move carries at most one move assessment, so setting one replaces
+    /// any other; toggling the same one again removes it
+    pub fn toggle_nag(&mut self, nag: Nag) {
+        if let Some(i) = self.nags.iter().position(|&n| n == nag) {
+            self.nags.remove(i);
+            return;
+        }
+        if nag.is_move_assessment() {
+            self.nags.retain(|n| !n.is_move_assessment());
+        }
+        self.add_nag(nag);
+    }
+}
+
+/// The outcome of a finished game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameResult::WhiteWins => write!(f, "1-0"),
+            GameResult::BlackWins => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+/// Why a game ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Checkmate => write!(f, "checkmate"),
+            Termination::Stalemate => write!(f, "stalemate"),
+            Termination::FiftyMoveRule => write!(f, "fifty-move rule"),
+            Termination::ThreefoldRepetition => write!(f, "threefold repetition"),
+            Termination::InsufficientMaterial => write!(f, "insufficient material"),
+        }
+    }
+}
+
+/// Whether the game is still being played, and how it ended if not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    Over(GameResult, Termination),
+}
+
+impl GameStatus {
+    /// Whether the game has ended.
+    pub fn is_over(&self) -> bool {
+        matches!(self, GameStatus::Over(_, _))
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "*"),
+            GameStatus::Over(result, termination) => write!(f, "{} {}", result, termination),
+        }
+    }
+}
+
+impl FenTree {
+    fn new(fen: Fen) -> Self {
+        let mut store = Arena::new();
+        let is_check = fen.board.king_check_cnt().check_cnt(fen.to_move) > 0;
+        let root_node = FenNode::root(fen, is_check, fen.status());
+        let root = store.insert(root_node);
+        Self {
+            store,
+            root,
+            curr: root,
+            positions: HashMap::from([(fen.hash, vec![root])]),
+        }
+    }
+
+    fn curr_fen(&self) -> &Fen {
+        &self.store.get(self.curr).fen
+    }
+
+    fn curr_is_check(&self) -> bool {
+        self.store.get(self.curr).is_check
+    }
+
+    fn curr_status(&self) -> GameStatus {
+        self.store.get(self.curr).status
+    }
+
+    fn apply_move(&mut self, mov: Move) {
+        if self.curr_status().is_over() {
+            return;
+        }
+        let children = &self.store.get(self.curr).children;
+        if let Some(&(_, idx)) = children.iter().find(|(m, _)| m == &mov) {
+            self.curr = idx;
+            return;
+        }
+
+        let new_curr = self.add_child(self.curr, mov);
+        self.store.get_mut(self.curr).next_child = Some((mov, new_curr));
+        self.curr = new_curr;
+    }
+
+    // Appends `mov` as the last variation after `parent`. It only becomes
+    // the line followed forward if `parent` had no continuation yet.
+    fn add_child(&mut self, parent: NodeId, mov: Move) -> NodeId {
+        let new_fen = self.store.get(parent).fen.apply_move(mov);
+        let new_is_check = mov.check_cnt > 0;
+        let mut new_status = new_fen.status();
+        if !new_status.is_over() && self.repetition_cnt(parent, &new_fen) >= 2 {
+            new_status = GameStatus::Over(GameResult::Draw, Termination::ThreefoldRepetition);
+        }
+        let new_node = FenNode::internal_node(new_fen, new_is_check, new_status, parent);
+        let idx = self.store.insert(new_node);
+        self.positions.entry(new_fen.hash).or_default().push(idx);
+        let parent_node = self.store.get_mut(parent);
+        parent_node.children.push((mov, idx));
+        parent_node.next_child.get_or_insert((mov, idx));
+        idx
+    }
+
+    // Number of times `fen` occurs in the line ending at `idx`,
+    // going back only as far as the last irreversible move
+    fn repetition_cnt(&self, idx: NodeId, fen: &Fen) -> usize {
+        let mut cnt = 0;
+        let mut clock = fen.halfmove_clock;
+        let mut ptr = Some(idx);
+        while let Some(idx) = ptr {
+            if clock == 0 {
+                break;
+            }
+            let node = self.store.get(idx);
+            if node.fen.is_same_position(fen) {
+                cnt += 1;
+            }
+            clock = node.fen.halfmove_clock;
+            ptr = node.parent;
+        }
+        cnt
+    }
+
+    fn unapply_move(&mut self) {
+        if let Some(parent) = self.store.get(self.curr).parent {
+            self.curr = parent;
+        }
+    }
+
+    fn next_move(&mut self) -> Option<Move> {
+        let curr = self.store.get(self.curr);
+        if let Some((mov, next_child)) = curr.next_child {
+            self.curr = next_child;
+            Some(mov)
+        } else if let Some(&(mov, child)) = curr.children.first() {
+            self.curr = child;
+            Some(mov)
+        } else {
+            None
+        }
+    }
+
+    fn next_variation(&mut self) {
+        let Some(parent) = self
+            .store
+            .get(self.curr)
+            .parent
+            .map(|idx| self.store.get_mut(idx))
+        else {
+            return;
+        };
+        let curr_idx = parent
+            .children
+            .iter()
+            .position(|(_, idx)| idx == &self.curr)
+            .unwrap();
+        if curr_idx == parent.children.len() - 1 {
+            return;
+        }
+        let (mov, idx) = parent.children[curr_idx + 1];
+        parent.next_child = Some((mov, idx));
+        self.curr = idx;
+    }
+
+    fn prev_variation(&mut self) {
+        let Some(parent) = self
+            .store
+            .get(self.curr)
+            .parent
+            .map(|idx| self.store.get_mut(idx))
+        else {
+            return;
+        };
+        let curr_idx = parent
+            .children
+            .iter()
+            .position(|(_, idx)| idx == &self.curr)
+            .unwrap();
+        if curr_idx == 0 {
+            return;
+        }
+        let (mov, idx) = parent.children[curr_idx - 1];
+        parent.next_child = Some((mov, idx));
+        self.curr = idx;
+    }
+
+    // Removes the current move along with everything after it
+    fn delete_variation(&mut self) {
+        let Some(parent_idx) = self.store.get(self.curr).parent else {
+            return;
+        };
+        let parent = self.store.get_mut(parent_idx);
+        parent.children.retain(|&(_, idx)| idx != self.curr);
+        if parent.next_child.is_some_and(|(_, idx)| idx == self.curr) {
+            parent.next_child = parent.children.first().copied();
+        }
+        self.delete_subtree(self.curr);
+        self.curr = parent_idx;
+    }
+
+    // Makes the line leading to the current move the main line all the way
+    // from the start of the game
+    fn promote_variation(&mut self) {
+        let mut ptr = self.curr;
+        while let Some(parent_idx) = self.store.get(ptr).parent {
+            let parent = self.store.get_mut(parent_idx);
+            let pos = parent
+                .children
+                .iter()
+                .position(|&(_, idx)| idx == ptr)
+                .unwrap();
+            let child = parent.children.remove(pos);
+            parent.children.insert(0, child);
+            parent.next_child = Some(child);
+            ptr = parent_idx;
+        }
+    }
+
+    // Moves the current move `up` (or down) one place among its siblings
+    fn reorder_variation(&mut self, up: bool) {
+        let Some(parent_idx) = self.store.get(self.curr).parent else {
+            return;
+        };
+        let children = &mut self.store.get_mut(parent_idx).children;
+        let pos = children
+            .iter()
+            .position(|&(_, idx)| idx == self.curr)
+            .unwrap();
+        if up && pos > 0 {
+            children.swap(pos, pos - 1);
+        } else if !up && pos + 1 < children.len() {
+            children.swap(pos, pos + 1);
+        }
+    }
+
+    fn truncate_after_curr(&mut self) {
+        let node = self.store.get_mut(self.curr);
+        let children = std::mem::take(&mut node.children);
+        node.next_child = None;
+        for (_, idx) in children {
+            self.delete_subtree(idx);
+        }
+    }
+
+    fn delete_subtree(&mut self, idx: NodeId) {
+        let mut stack = vec![idx];
+        while let Some(idx) = stack.pop() {
+            let node = self.store.delete(idx).unwrap();
+            stack.extend(node.children.iter().map(|&(_, child)| child));
+            if let Some(nodes) = self.positions.get_mut(&node.fen.hash) {
+                nodes.retain(|&n| n != idx);
+            }
+        }
+    }
+
+    // Other nodes reached by a move where the position at `node` occurs
+    fn transpositions(&self, node: NodeId) -> Vec<GameMove> {
+        let fen = &self.store.get(node).fen;
+        let Some(nodes) = self.positions.get(&fen.hash) else {
+            return Vec::new();
+        };
+        nodes
+            .iter()
+            .filter(|&&other| other != node)
+            .filter_map(|&other| {
+                let other_node = self.store.get(other);
+                let parent = other_node.parent?;
+                if !other_node.fen.is_same_position(fen) {
+                    return None;
+                }
+                let &(mov, _) = self
+                    .store
+                    .get(parent)
+                    .children
+                    .iter()
+                    .find(|&&(_, child)| child == other)?;
+                Some(self.game_move(parent, mov, other))
+            })
+            .collect()
+    }
+
+    fn goto(&mut self, node: NodeId) -> bool {
+        if self.store.try_get(node).is_none() {
+            return false;
+        }
+        self.curr = node;
+        true
+    }
+
+    fn goto_end(&mut self) {
+        while let Some((_, next)) = self.store.get(self.curr).next_child {
+            self.curr = next;
+        }
+    }
+
+    fn path_to_curr(&self) -> Vec<NodeId> {
+        let mut path = Vec::new();
+        let mut ptr = self.curr;
+        while let Some(parent) = self.store.get(ptr).parent {
+            path.push(ptr);
+            ptr = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    fn game_move(&self, parent: NodeId, mov: Move, node: NodeId) -> GameMove {
+        let fen = &self.store.get(parent).fen;
+        GameMove {
+            mov,
+            node,
+            is_curr: node == self.curr,
+            move_num: fen.move_cnt,
+            notation: fen.move_string(mov),
+            annotation: self.store.get(node).annotation.clone(),
+        }
+    }
+
+    fn game_moves(&self) -> Vec<GameMove> {
+        let mut moves = Vec::new();
+        let mut ptr = self.root;
+        while let Some((mov, next)) = self.store.get(ptr).next_child {
+            moves.push(self.game_move(ptr, mov, next));
+            ptr = next;
+        }
+        moves
+    }
+
+    fn continuations(&self, node: NodeId) -> Vec<GameMove> {
+        let node_ref = self.store.get(node);
+        let Some((mov, next)) = node_ref.next_child else {
+            return Vec::new();
+        };
+        let mut moves = vec![self.game_move(node, mov, next)];
+        for &(mov, child) in node_ref.children.iter().filter(|&&(_, i)| i != next) {
+            moves.push(self.game_move(node, mov, child));
+        }
+        moves
+    }
+}
+
+/// A game as a tree of moves, with variations, annotations and PGN tags, and
+/// a current position that moves are played from.
+#[derive(Debug)]
+pub struct Game {
+    tree: FenTree,
+    tags: Vec<(String, String)>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// A game from the standard starting position.
+    pub fn new() -> Self {
+        Self::from_fen(INIT_FEN).unwrap()
+    }
+
+    /// A game starting from the given position.
+    pub fn from_fen(fen: &str) -> Result<Self, FenParseError> {
+        let fen = fen.parse::<Fen>()?;
+        let tree = FenTree::new(fen);
+        Ok(Self {
+            tree,
+            tags: Vec::new(),
+        })
+    }
+
+    /// Value of the PGN tag `name`, if the game has it.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// FEN of the current position.
+    pub fn current_fen(&self) -> String {
+        self.tree.curr_fen().to_string()
+    }
+
+    /// Board of the current position.
+    pub fn board(&self) -> &Board {
+        &self.tree.curr_fen().board
+    }
+
+    /// The side to move in the current position.
+    pub fn to_move(&self) -> Colour {
+        self.tree.curr_fen().to_move
+    }
+
+    /// Legal moves of the piece on the square, by target square. Of the moves
+    /// promoting on a square only the queen promotion is kept.
+    pub fn legal_moves(&self, rank: usize, file: usize) -> HashMap<(usize, usize), Move> {
+        self.tree.curr_fen().legal_moves(rank, file)
+    }
+
+    /// Every legal move in the current position.
+    pub fn all_legal_moves(&self) -> Vec<Move> {
+        self.tree.curr_fen().generate_moves()
+    }
+
+    /// Parses a move in standard algebraic notation, such as `Nf3` or
+    /// `exd8=Q+`, in the current position.
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveParseError> {
+        self.tree.curr_fen().parse_san(san)
+    }
+
+    /// Parses a move in UCI notation, such as `g1f3` or `e7d8q`, in the current
+    /// position.
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, MoveParseError> {
+        self.tree.curr_fen().parse_uci(uci)
+    }
+
+    /// Plays the move from the current position. A move already in the tree is
+    /// followed rather than added again. Does nothing once the game is over.
+    pub fn apply_move(&mut self, mov: Move) {
+        self.tree.apply_move(mov);
+    }
+
+    /// Goes back one move.
+    pub fn back(&mut self) {
+        self.tree.unapply_move();
+    }
+
+    /// Goes forward along the current line, returning the move played.
+    pub fn forward(&mut self) -> Option<Move> {
+        self.tree.next_move()
+    }
+
+    /// Replaces the last move with the next variation played instead of it.
+    pub fn next_variation(&mut self) {
+        self.tree.next_variation();
+    }
+
+    /// Replaces the last move with the previous variation played instead of it.
+    pub fn prev_variation(&mut self) {
+        self.tree.prev_variation();
+    }
+
+    /// Removes the last move along with everything after it.
+    pub fn delete_variation(&mut self) {
+        self.tree.delete_variation();
+    }
+
+    /// Makes the line leading to the current position the main line all the
+    /// way from the start of the game.
+    pub fn promote_variation_to_mainline(&mut self) {
+        self.tree.promote_variation();
+    }
+
+    /// Moves the last move `up` (or down) one place among its alternatives.
+    pub fn reorder_variation(&mut self, up: bool) {
+        self.tree.reorder_variation(up);
+    }
+
+    /// Removes every move after the current position.
+    pub fn truncate_after_current(&mut self) {
+        self.tree.truncate_after_curr();
+    }
+
+    /// Whether the side to move is in check.
+    pub fn is_check(&self) -> bool {
+        self.tree.curr_is_check()
+    }
+
+    /// Whether the game is over at the current position, and how.
+    pub fn status(&self) -> GameStatus {
+        self.tree.curr_status()
+    }
+
+    /// Square of the king of the side to move.
+    pub fn king_position(&self) -> (usize, usize) {
+        let fen = self.tree.curr_fen();
+        fen.board.king_position(fen.to_move)
+    }
+
+    /// The main line, followed forward from the start
+    pub fn game_moves(&self) -> Vec<GameMove> {
+        self.tree.game_moves()
+    }
+
+    /// Moves elsewhere in the tree that lead to the current position
+    pub fn transpositions(&self) -> Vec<GameMove> {
+        self.tree.transpositions(self.tree.curr)
+    }
+
+    /// Moves continuing from `node`: the one followed forward and then the
+    /// other variations. Empty if the node has been deleted.
+    pub fn continuations(&self, node: NodeId) -> Vec<GameMove> {
+        if self.tree.store.try_get(node).is_none() {
+            return Vec::new();
+        }
+        self.tree.continuations(node)
+    }
+
+    /// Whether the current position is the starting one.
+    pub fn at_start(&self) -> bool {
+        self.tree.curr == self.tree.root
+    }
+
+    /// Node of the starting position.
+    pub fn root(&self) -> NodeId {
+        self.tree.root
+    }
+
+    /// Node of the current position.
+    pub fn current(&self) -> NodeId {
+        self.tree.curr
+    }
+
+    /// Moves continuing from `node`, in variation order. `None` if the node
+    /// has been deleted.
+    pub fn children(&self, node: NodeId) -> Option<&[(Move, NodeId)]> {
+        self.tree.store.try_get(node).map(|n| n.children.as_slice())
+    }
+
+    /// Node before `node`. `None` at the start or if the node has been deleted.
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.tree.store.try_get(node).and_then(|n| n.parent)
+    }
+
+    /// Makes `node` the current position, returning false if it has been deleted
+    pub fn goto(&mut self, node: NodeId) -> bool {
+        self.tree.goto(node)
+    }
+
+    /// Makes the starting position the current one.
+    pub fn goto_start(&mut self) {
+        self.tree.curr = self.tree.root;
+    }
+
+    /// Follows the current line forward to its last move
+    pub fn goto_end(&mut self) {
+        self.tree.goto_end();
+    }
+
+    /// Nodes of the moves played from the start to reach the current position
+    pub fn path_to_current(&self) -> Vec<NodeId> {
+        self.tree.path_to_curr()
+    }
+
+    /// Annotation of the last move played; at the start of the game this holds
+    /// the comment on the game as a whole
+    pub fn annotation(&self) -> &Annotation {
+        &self.tree.store.get(self.tree.curr).annotation
+    }
+
+    /// Annotation of the last move played, for editing.
+    pub fn annotation_mut(&mut self) -> &mut Annotation {
+        &mut self.tree.store.get_mut(self.tree.curr).annotation
+    }
+}
+
+/// Move generation backends, which produce identical moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveGen {
+    Bitboard,
+    Mailbox,
+}
+
+/// A position as described by Forsyth-Edwards Notation: the board, side to
+/// move, castling rights, en passant square and clocks. Parsed with
+/// `str::parse` and written back out with `to_string`.
+#[derive(Debug, Clone, Copy)]
+pub struct Fen {
+    board: Board,
+    to_move: Colour,
+    white_king_castle: bool,
+    white_queen_castle: bool,
+    black_king_castle: bool,
+    black_queen_castle: bool,
+    en_passant: Option<(usize, usize)>,
+    halfmove_clock: u32,
+    move_cnt: u32,
+    // Zobrist hash of everything but the clocks, kept up to date by apply_move
+    hash: u64,
+}
+
+/// A legal move, along with what it captures and whether it gives check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Move {
+    piece: Piece,
+    colour: Colour,
+    from: (usize, usize),
+    to: (usize, usize),
+    capture: Option<CapturedPiece>,
+    check_cnt: usize,
+    may_promote: bool,
+    promotion: Option<(Piece, Colour)>,
+}
+
+impl Move {
+    /// Whether the move captures a piece.
+    pub fn has_capture(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Whether the move gives check.
+    pub fn has_check(&self) -> bool {
+        self.check_cnt > 0
+    }
+
+    /// Whether the move takes a pawn to the last rank.
+    pub fn may_promote(&self) -> bool {
+        self.may_promote
+    }
+
+    /// The piece a pawn promotes to.
+    pub fn promotion(&self) -> Option<Piece> {
+        self.promotion.map(|(piece, _)| piece)
+    }
+
+    /// Square the piece moves from.
+    pub fn from(&self) -> (usize, usize) {
+        self.from
+    }
+
+    /// Square the piece moves to.
+    pub fn to(&self) -> (usize, usize) {
+        self.to
+    }
+
+    /// The side making the move.
+    pub fn colour(&self) -> Colour {
+        self.colour
+    }
+
+    /// Long algebraic notation as used by UCI, such as e2e4 or a7a8q
+    pub fn uci(&self) -> String {
+        let square = |(rank, file)| format!("{}{}", file_to_char(file), rank_to_char(rank));
+        let mut uci = square(self.from) + &square(self.to);
+        if let Some(c) = self.promotion().and_then(Piece::to_char) {
+            uci.push(c.to_ascii_lowercase());
+        }
+        uci
+    }
+
+    fn is_king_castle(&self) -> bool {
+        self.piece == Piece::King && self.from.1 == 4 && self.to.1 == 6
+    }
+
+    fn is_queen_castle(&self) -> bool {
+        self.piece == Piece::King && self.from.1 == 4 && self.to.1 == 2
+    }
+}
+
+/// Ways of writing a move down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Standard algebraic notation, such as `Nf3` or `exd8=Q+`.
+    San,
+    /// Long algebraic notation, such as `Ng1-f3` or `e7xd8=Q+`.
+    Lan,
+    /// UCI notation, such as `g1f3` or `e7d8q`.
+    Uci,
+}
+
+/// The state make_move overwrites, for unmake_move to restore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoInfo {
+    mov: Move,
+    castling: [bool; 4],
+    en_passant: Option<(usize, usize)>,
+    halfmove_clock: u32,
+    move_cnt: u32,
+    hash: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct CapturedPiece {
+    piece: Piece,
+    colour: Colour,
+    pos: (usize, usize),
+}
+
+impl Fen {
+    /// The side to move.
+    pub fn to_move(&self) -> Colour {
+        self.to_move
+    }
+
+    fn move_string(&self, mov: Move) -> String {
+        let mut ms = if mov.is_king_castle() {
+            "O-O".to_string()
+        } else if mov.is_queen_castle() {
+            "O-O-O".to_string()
+        } else {
+            let mut ms = String::new();
+            if let Some(pc) = mov.piece.to_char() {
+                ms.push(pc);
+                ms.push_str(&self.disambiguation(mov));
+            } else if mov.capture.is_some() {
+                ms.push(file_to_char(mov.from.1));
+            }
+            if mov.capture.is_some() {
+                ms.push('x');
+            }
+            ms.push(file_to_char(mov.to.1));
+            ms.push(rank_to_char(mov.to.0));
+            if let Some(pc) = mov.promotion().and_then(Piece::to_char) {
+                ms.push('=');
+                ms.push(pc);
+            }
+            ms
+        };
+        ms.push_str(self.check_suffix(mov));
+        ms
+    }
+
+    // Long algebraic notation without the check suffix, such as Ng1-f3
+    fn lan_string(&self, mov: Move) -> String {
+        if mov.is_king_castle() {
+            return "O-O".to_string();
+        } else if mov.is_queen_castle() {
+            return "O-O-O".to_string();
+        }
+        let mut ms = String::new();
+        if let Some(pc) = mov.piece.to_char() {
+            ms.push(pc);
+        }
+        ms.push(file_to_char(mov.from.1));
+        ms.push(rank_to_char(mov.from.0));
+        ms.push(if mov.capture.is_some() { 'x' } else { '-' });
+        ms.push(file_to_char(mov.to.1));
+        ms.push(rank_to_char(mov.to.0));
+        if let Some(pc) = mov.promotion().and_then(Piece::to_char) {
+            ms.push('=');
+            ms.push(pc);
+        }
+        ms
+    }
+
+    fn check_suffix(&self, mov: Move) -> &'static str {
+        if self.apply_move(mov).is_mate() {
+            "#"
+        } else if mov.check_cnt > 0 {
+            "+"
+        } else {
+            ""
+        }
+    }
+
+    /// The move written in the given notation.
+    pub fn notation(&self, mov: Move, notation: Notation) -> String {
+        match notation {
+            Notation::San => self.move_string(mov),
+            Notation::Lan => self.lan_string(mov) + self.check_suffix(mov),
+            Notation::Uci => mov.uci(),
+        }
+    }
+
+    /// Parses a move written in any of the notations. Errors are those of
+    /// reading it as SAN.
+    pub fn parse_move(&self, text: &str) -> Result<Move, MoveParseError> {
+        self.parse_san(text).or_else(|err| {
+            self.parse_uci(text)
+                .or_else(|_| self.parse_lan(text))
+                .map_err(|_| err)
+        })
+    }
+
+    /// Parses a move in standard algebraic notation, such as `Nf3` or
+    /// `exd8=Q+`.
+    pub fn parse_san(&self, san: &str) -> Result<Move, MoveParseError> {
+        let syntax_err = || MoveParseError::InvalidSyntax(san.to_string());
+        let text = san.trim_end_matches(['+', '#', '!', '?']);
+        if !text.is_ascii() {
+            return Err(syntax_err());
+        }
+
+        let candidates: Vec<_> = if text == "O-O" || text == "0-0" {
+            self.generate_moves()
+                .into_iter()
+                .filter(Move::is_king_castle)
+                .collect()
+        } else if text == "O-O-O" || text == "0-0-0" {
+            self.generate_moves()
+                .into_iter()
+                .filter(Move::is_queen_castle)
+                .collect()
+        } else {
+            let (piece, rest) = match text.chars().next().and_then(Piece::from_char) {
+                Some(piece) => (piece, &text[1..]),
+                None => (Piece::Pawn, text),
+            };
+            // Promotions are accepted both as "e8=Q" and as "e8Q"
+            let (rest, promotion) = match rest.chars().last().and_then(Piece::from_char) {
+                Some(promotion) if piece == Piece::Pawn => {
+                    let rest = &rest[..rest.len() - 1];
+                    (rest.strip_suffix('=').unwrap_or(rest), Some(promotion))
+                }
+                _ => (rest, None),
+            };
+            if rest.len() < 2 {
+                return Err(syntax_err());
+            }
+            let (prefix, dest) = rest.split_at(rest.len() - 2);
+            let to = parse_square(dest).map_err(|_| syntax_err())?;
+            let prefix = prefix.strip_suffix('x').unwrap_or(prefix);
+            let mut from_file = None;
+            let mut from_rank = None;
+            for b in prefix.bytes() {
+                match b {
+                    b'a'..=b'h' if from_file.is_none() => from_file = Some((b - b'a') as usize),
+                    b'1'..=b'8' if from_rank.is_none() => {
+                        from_rank = Some(BOARD_SIZE - (b - b'0') as usize)
+                    }
+                    _ => return Err(syntax_err()),
+                }
+            }
+            let moves: Vec<_> = self
+                .generate_moves()
+                .into_iter()
+                .filter(|m| {
+                    m.piece == piece
+                        && m.to == to
+                        && from_file.is_none_or(|f| m.from.1 == f)
+                        && from_rank.is_none_or(|r| m.from.0 == r)
+                        && promotion.is_none_or(|p| m.promotion() == Some(p))
+                })
+                .collect();
+            // Rather than ambiguous between the pieces it could become
+            if promotion.is_none() && moves.first().is_some_and(|m| m.promotion().is_some()) {
+                return Err(MoveParseError::MissingPromotion(san.to_string()));
+            }
+            moves
+        };
+
+        match candidates[..] {
+            [] => Err(MoveParseError::Illegal(san.to_string())),
+            [mov] => Ok(mov),
+            _ => Err(MoveParseError::Ambiguous(san.to_string())),
+        }
+    }
+
+    /// Parses a move in long algebraic notation, such as `Ng1-f3` or
+    /// `e7xd8=Q+`.
+    pub fn parse_lan(&self, lan: &str) -> Result<Move, MoveParseError> {
+        let text = lan.trim_end_matches(['+', '#', '!', '?']);
+        self.generate_moves()
+            .into_iter()
+            .find(|&m| self.lan_string(m) == text)
+            .ok_or_else(|| MoveParseError::Illegal(lan.to_string()))
+    }
+
+    /// Parses a move in UCI notation, such as `g1f3` or `e7d8q`.
+    pub fn parse_uci(&self, uci: &str) -> Result<Move, MoveParseError> {
+        let syntax_err = || MoveParseError::InvalidSyntax(uci.to_string());
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(syntax_err());
+        }
+        let from = parse_square(&uci[0..2]).map_err(|_| syntax_err())?;
+        let to = parse_square(&uci[2..4]).map_err(|_| syntax_err())?;
+        let promotion = match uci[4..].chars().next() {
+            Some(c) => match Piece::from_char(c.to_ascii_uppercase()) {
+                Some(piece) if piece != Piece::King => Some(piece),
+                _ => return Err(syntax_err()),
+            },
+            None => None,
+        };
+        self.generate_moves()
+            .into_iter()
+            .find(|m| m.from == from && m.to == to && m.promotion() == promotion)
+            .ok_or_else(|| MoveParseError::Illegal(uci.to_string()))
+    }
+
+    // The origin file, rank or square needed to tell `mov` apart from
+    // other legal moves of the same piece type to the same square
+    fn disambiguation(&self, mov: Move) -> String {
+        let others: Vec<_> = self
+            .generate_moves()
+            .into_iter()
+            .filter(|m| m.piece == mov.piece && m.to == mov.to && m.from != mov.from)
+            .collect();
+        let (rank, file) = mov.from;
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|m| m.from.1 != file) {
+            file_to_char(file).to_string()
+        } else if others.iter().all(|m| m.from.0 != rank) {
+            rank_to_char(rank).to_string()
+        } else {
+            format!("{}{}", file_to_char(file), rank_to_char(rank))
+        }
+    }
+
+    fn is_mate(&self) -> bool {
+        let kic = self.board.king_check_cnt();
+        kic.check_cnt(self.to_move) != 0 && self.generate_moves().is_empty()
+    }
+
+    fn status(&self) -> GameStatus {
+        if self.generate_moves().is_empty() {
+            if self.board.king_check_cnt().check_cnt(self.to_move) == 0 {
+                GameStatus::Over(GameResult::Draw, Termination::Stalemate)
+            } else if self.to_move == Colour::White {
+                GameStatus::Over(GameResult::BlackWins, Termination::Checkmate)
+            } else {
+                GameStatus::Over(GameResult::WhiteWins, Termination::Checkmate)
+            }
+        } else if self.board.has_insufficient_material() {
+            GameStatus::Over(GameResult::Draw, Termination::InsufficientMaterial)
+        } else if self.halfmove_clock >= 100 {
+            GameStatus::Over(GameResult::Draw, Termination::FiftyMoveRule)
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    fn castling_rights(&self) -> [bool; 4] {
+        [
+            self.white_king_castle,
+            self.white_queen_castle,
+            self.black_king_castle,
+            self.black_queen_castle,
+        ]
+    }
+
+    fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (rank, file) in iproduct!(0..BOARD_SIZE, 0..BOARD_SIZE) {
+            if let Position::Occupied(piece, colour) = self.board[rank][file] {
+                hash ^= zobrist::piece(piece, colour, (rank, file));
+            }
+        }
+        if self.to_move == Colour::Black {
+            hash ^= zobrist::black_to_move();
+        }
+        hash ^ zobrist::castling(self.castling_rights())
+            ^ zobrist::en_passant(self.capturable_en_passant())
+    }
+
+    // The en-passant square, if a pawn of the side to move can legally take on
+    // it. Positions only differ by their en-passant square when it can be used.
+    fn capturable_en_passant(&self) -> Option<(usize, usize)> {
+        let (rank, file) = self.en_passant?;
+        // The pawn that double-stepped stands just beyond the square
+        let pawn_rank = match self.to_move {
+            Colour::White => rank + 1,
+            Colour::Black => rank - 1,
+        };
+        let pawn = Position::Occupied(Piece::Pawn, self.to_move);
+        let capturable = [file.wrapping_sub(1), file + 1]
+            .into_iter()
+            .filter(|&from| from < BOARD_SIZE && self.board[pawn_rank][from] == pawn)
+            .any(|from| {
+                let mut board = self.board;
+                board[pawn_rank][from] = Position::Empty;
+                board[pawn_rank][file] = Position::Empty;
+                board[rank][file] = pawn;
+                let (king_rank, king_file) = board.king_position(self.to_move);
+                !board.is_attacked(king_rank, king_file, self.to_move.opposite())
+            });
+        capturable.then_some((rank, file))
+    }
+
+    fn is_same_position(&self, other: &Fen) -> bool {
+        self.hash == other.hash
+            && self.board == other.board
+            && self.to_move == other.to_move
+            && self.white_king_castle == other.white_king_castle
+            && self.white_queen_castle == other.white_queen_castle
+            && self.black_king_castle == other.black_king_castle
+            && self.black_queen_castle == other.black_queen_castle
+            && self.capturable_en_passant() == other.capturable_en_passant()
+    }
+
+    fn apply_move(&self, mov: Move) -> Fen {
+        let mut fen = *self;
+        fen.make_move(mov);
+        fen
+    }
+
+    /// Plays the move in place, returning what unmake_move needs to take it back
+    pub fn make_move(&mut self, mov: Move) -> UndoInfo {
+        let undo = UndoInfo {
+            mov,
+            castling: self.castling_rights(),
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            move_cnt: self.move_cnt,
+            hash: self.hash,
+        };
+        let en_passant = self.capturable_en_passant();
+
+        if mov.colour == Colour::Black {
+            self.move_cnt += 1;
+        }
+        self.board.apply_move(mov);
+        self.to_move = self.to_move.opposite();
+
+        self.hash ^= zobrist::black_to_move();
+        self.hash ^= zobrist::piece(mov.piece, mov.colour, mov.from);
+        if let Some(cap) = mov.capture {
+            self.hash ^= zobrist::piece(cap.piece, cap.colour, cap.pos);
+        }
+        let (piece, colour) = mov.promotion.unwrap_or((mov.piece, mov.colour));
+        self.hash ^= zobrist::piece(piece, colour, mov.to);
+        let rank = mov.from.0;
+        if mov.is_king_castle() {
+            self.hash ^= zobrist::piece(Piece::Rook, mov.colour, (rank, 7));
+            self.hash ^= zobrist::piece(Piece::Rook, mov.colour, (rank, 5));
+        } else if mov.is_queen_castle() {
+            self.hash ^= zobrist::piece(Piece::Rook, mov.colour, (rank, 0));
+            self.hash ^= zobrist::piece(Piece::Rook, mov.colour, (rank, 3));
+        }
+
+        // Update castling
+        if mov.colour == Colour::White {
+            if mov.piece == Piece::King {
+                self.white_king_castle = false;
+                self.white_queen_castle = false;
+            }
+            if mov.piece == Piece::Rook && mov.from == (7, 7) {
+                self.white_king_castle = false;
+            }
+            if mov.piece == Piece::Rook && mov.from == (7, 0) {
+                self.white_queen_castle = false;
+            }
+        } else {
+            if mov.piece == Piece::King {
+                self.black_king_castle = false;
+                self.black_queen_castle = false;
+            }
+            if mov.piece == Piece::Rook && mov.from == (0, 7) {
+                self.black_king_castle = false;
+            }
+            if mov.piece == Piece::Rook && mov.from == (0, 0) {
+                self.black_queen_castle = false;
+            }
+        }
+        // A captured rook takes its castling right with it
+        match mov.capture.map(|cap| cap.pos) {
+            Some((7, 7)) => self.white_king_castle = false,
+            Some((7, 0)) => self.white_queen_castle = false,
+            Some((0, 7)) => self.black_king_castle = false,
+            Some((0, 0)) => self.black_queen_castle = false,
+            _ => {}
+        }
+
+        // Update en-passant
+        if mov.piece == Piece::Pawn {
+            if mov.colour == Colour::White && mov.to.0 == 4 && mov.from.0 == 6 {
+                self.en_passant = Some((5, mov.to.1));
+            } else if mov.colour == Colour::Black && mov.to.0 == 3 && mov.from.0 == 1 {
+                self.en_passant = Some((2, mov.to.1));
+            } else {
+                self.en_passant = None;
+            }
+        } else {
+            self.en_passant = None;
+        }
+
+        self.hash ^= zobrist::castling(undo.castling);
+        self.hash ^= zobrist::castling(self.castling_rights());
+        self.hash ^= zobrist::en_passant(en_passant);
+        self.hash ^= zobrist::en_passant(self.capturable_en_passant());
+
+        if mov.piece != Piece::Pawn && mov.capture.is_none() {
+            self.halfmove_clock += 1;
+        } else {
+            self.halfmove_clock = 0;
+        }
+
+        undo
+    }
+
+    /// Takes back the move `undo` was returned for, restoring the position
+    /// exactly.
+    pub fn unmake_move(&mut self, undo: UndoInfo) {
+        self.board.unapply_move(undo.mov);
+        self.to_move = undo.mov.colour;
+        [
+            self.white_king_castle,
+            self.white_queen_castle,
+            self.black_king_castle,
+            self.black_queen_castle,
+        ] = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.move_cnt = undo.move_cnt;
+        self.hash = undo.hash;
+    }
+
+    /// Every legal move in the position.
+    pub fn generate_moves(&self) -> Vec<Move> {
+        self.generate_moves_with(MoveGen::Bitboard)
+    }
+
+    /// Every legal move in the position, from the given generator.
+    pub fn generate_moves_with(&self, gen: MoveGen) -> Vec<Move> {
+        match gen {
+            MoveGen::Bitboard => self.generate_moves_bitboard(),
+            MoveGen::Mailbox => self.generate_moves_mailbox(),
+        }
+    }
+
+    // The original square by square generator
+    fn generate_moves_mailbox(&self) -> Vec<Move> {
+        iproduct!(0..BOARD_SIZE, 0..BOARD_SIZE)
+            .filter(|&(r, f)| {
+                matches!(self.board[r][f],
+                Position::Occupied(_, colour) if colour == self.to_move)
+            })
+            .flat_map(|(r, f)| self.piece_moves(r, f))
+            .collect()
+    }
+
+    /// Legal moves of the piece on the square, by target square. Of the moves
+    /// promoting on a square only the queen promotion is kept.
+    pub fn legal_moves(&self, rank: usize, file: usize) -> HashMap<(usize, usize), Move> {
+        // For promotions the first (queen) promotion stands in for the square
+        let mut moves = HashMap::new();
+        for mov in self.generate_moves() {
+            if mov.from == (rank, file) {
+                moves.entry(mov.to).or_insert(mov);
+            }
+        }
+        moves
+    }
+
+    fn piece_moves(&self, rank: usize, file: usize) -> Vec<Move> {
+        let Position::Occupied(piece, colour) = self.board[rank][file] else {
+            return Vec::new();
+        };
+
+        let positions = match piece {
+            Piece::Knight => knight_distance_positions(rank, file),
+            Piece::King => {
+                let mut positions = king_distance_positions(rank, file);
+                positions.extend(self.castle_positions(rank, file, colour));
+                positions
+            }
+            Piece::Rook => self.rook_move_positions(rank, file, colour),
+            Piece::Bishop => self.bishop_move_positions(rank, file, colour),
+            Piece::Queen => {
+                let mut pos = self.rook_move_positions(rank, file, colour);
+                pos.extend(self.bishop_move_positions(rank, file, colour));
+                pos
+            }
+            Piece::Pawn => {
+                let mut positions = Vec::new();
+                if colour == Colour::White {
+                    if rank > 0 && matches!(self.board[rank - 1][file], Position::Empty) {
+                        positions.push((rank - 1, file));
+                    }
+                    if rank == 6
+                        && matches!(self.board[rank - 1][file], Position::Empty)
+                        && matches!(self.board[rank - 2][file], Position::Empty)
+                    {
+                        positions.push((rank - 2, file));
+                    }
+                    if rank > 0
+                        && file > 0
+                        && matches!(self.board[rank - 1][file - 1], Position::Occupied(_, nc) if nc == colour.opposite())
+                    {
+                        positions.push((rank - 1, file - 1));
+                    }
+                    if rank > 0
+                        && file < BOARD_SIZE - 1
+                        && matches!(self.board[rank - 1][file + 1], Position::Occupied(_, nc) if nc == colour.opposite())
+                    {
+                        positions.push((rank - 1, file + 1));
+                    }
+                } else {
+                    if rank < BOARD_SIZE - 1
+                        && matches!(self.board[rank + 1][file], Position::Empty)
+                    {
+                        positions.push((rank + 1, file));
+                    }
+                    if rank == 1
+                        && matches!(self.board[rank + 1][file], Position::Empty)
+                        && matches!(self.board[rank + 2][file], Position::Empty)
+                    {
+                        positions.push((rank + 2, file));
+                    }
+                    if rank < BOARD_SIZE - 1
+                        && file > 0
+                        && matches!(self.board[rank + 1][file - 1], Position::Occupied(_, nc) if nc == colour.opposite())
+                    {
+                        positions.push((rank + 1, file - 1));
+                    }
+                    if rank < BOARD_SIZE - 1
+                        && file < BOARD_SIZE - 1
+                        && matches!(self.board[rank + 1][file + 1], Position::Occupied(_, nc) if nc == colour.opposite())
+                    {
+                        positions.push((rank + 1, file + 1));
+                    }
+                }
+                let mut moves = positions
+                    .into_iter()
+                    .filter_map(|(nr, nf)| self.board.make_move(piece, colour, rank, file, nr, nf))
+                    .collect::<Vec<_>>();
+                if let Some((epr, epf)) = self.en_passant {
+                    if epr == 2
+                        && colour == Colour::White
+                        && rank == 3
+                        && (file + 1 == epf || file == epf + 1)
+                    {
+                        let mut mov = Move {
+                            piece,
+                            colour,
+                            from: (rank, file),
+                            to: (epr, epf),
+                            capture: Some(CapturedPiece {
+                                piece: Piece::Pawn,
+                                colour: Colour::Black,
+                                pos: (3, epf),
+                            }),
+                            check_cnt: 0,
+                            may_promote: false,
+                            promotion: None,
+                        };
+                        if self.board.move_verify_checks(&mut mov) {
+                            moves.push(mov);
+                        }
+                    } else if epr == 5
+                        && colour == Colour::Black
+                        && rank == 4
+                        && (file + 1 == epf || file == epf + 1)
+                    {
+                        let mut mov = Move {
+                            piece,
+                            colour,
+                            from: (rank, file),
+                            to: (epr, epf),
+                            capture: Some(CapturedPiece {
+                                piece: Piece::Pawn,
+                                colour: Colour::White,
+                                pos: (4, epf),
+                            }),
+                            check_cnt: 0,
+                            may_promote: false,
+                            promotion: None,
+                        };
+                        if self.board.move_verify_checks(&mut mov) {
+                            moves.push(mov);
+                        }
+                    }
+                }
+                const PROMOTIONS: [Piece; 4] =
+                    [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+                return moves
+                    .into_iter()
+                    .flat_map(|mov| {
+                        if (mov.colour == Colour::White && mov.to.0 == 0)
+                            || (mov.colour == Colour::Black && mov.to.0 == 7)
+                        {
+                            PROMOTIONS
+                                .into_iter()
+                                .filter_map(|p| {
+                                    let mut mov = mov;
+                                    mov.may_promote = true;
+                                    mov.promotion = Some((p, mov.colour));
+                                    self.board.move_verify_checks(&mut mov).then_some(mov)
+                                })
+                                .collect()
+                        } else {
+                            vec![mov]
+                        }
+                    })
+                    .collect();
+            }
+        };
+
+        positions
+            .into_iter()
+            .filter_map(|(nr, nf)| self.board.make_move(piece, colour, rank, file, nr, nf))
+            .collect()
+    }
+
+    fn castle_positions(&self, rank: usize, file: usize, colour: Colour) -> Vec<(usize, usize)> {
+        let (home, king_castle, queen_castle) = match colour {
+            Colour::White => (7, self.white_king_castle, self.white_queen_castle),
+            Colour::Black => (0, self.black_king_castle, self.black_queen_castle),
+        };
+        let mut positions = Vec::new();
+        // Cannot castle out of check
+        if (rank, file) != (home, 4) || self.board.is_attacked(home, 4, colour.opposite()) {
+            return positions;
+        }
+        let is_rook = |f: usize| matches!(self.board[home][f], Position::Occupied(Piece::Rook, rc) if rc == colour);
+        let is_empty = |f: usize| matches!(self.board[home][f], Position::Empty);
+        let is_safe = |f: usize| !self.board.is_attacked(home, f, colour.opposite());
+        // The king may not pass through or land on an attacked square
+        if king_castle && is_rook(7) && is_empty(5) && is_empty(6) && is_safe(5) && is_safe(6) {
+            positions.push((home, 6));
+        }
+        if queen_castle
+            && is_rook(0)
+            && is_empty(1)
+            && is_empty(2)
+            && is_empty(3)
+            && is_safe(3)
+            && is_safe(2)
+        {
+            positions.push((home, 2));
+        }
+        positions
+    }
+
+    fn rook_move_positions(&self, rank: usize, file: usize, colour: Colour) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        for nr in (0..rank).rev() {
+            let pos = self.board[nr][file];
+            match pos {
+                Position::Empty => positions.push((nr, file)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((nr, file));
+                    }
+                    break;
+                }
+            }
+        }
+        for nr in rank + 1..BOARD_SIZE {
+            let pos = self.board[nr][file];
+            match pos {
+                Position::Empty => positions.push((nr, file)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((nr, file));
+                    }
+                    break;
+                }
+            }
+        }
+        for nf in (0..file).rev() {
+            let pos = self.board[rank][nf];
+            match pos {
+                Position::Empty => positions.push((rank, nf)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((rank, nf));
+                    }
+                    break;
+                }
+            }
+        }
+        for nf in file + 1..BOARD_SIZE {
+            let pos = self.board[rank][nf];
+            match pos {
+                Position::Empty => positions.push((rank, nf)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((rank, nf));
+                    }
+                    break;
+                }
+            }
+        }
+        positions
+    }
+
+    fn bishop_move_positions(
+        &self,
+        rank: usize,
+        file: usize,
+        colour: Colour,
+    ) -> Vec<(usize, usize)> {
+        let mut positions = Vec::new();
+        for diff in 1..=rank.min(file) {
+            let nr = rank - diff;
+            let nf = file - diff;
+            let pos = self.board[nr][nf];
+            match pos {
+                Position::Empty => positions.push((nr, nf)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((nr, nf));
+                    }
+                    break;
+                }
+            }
+        }
+        for diff in 1..(BOARD_SIZE - rank).min(BOARD_SIZE - file) {
+            let nr = rank + diff;
+            let nf = file + diff;
+            let pos = self.board[nr][nf];
+            match pos {
+                Position::Empty => positions.push((nr, nf)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((nr, nf));
+                    }
+                    break;
+                }
+            }
+        }
+        for diff in 1..(rank + 1).min(BOARD_SIZE - file) {
+            let nr = rank - diff;
+            let nf = file + diff;
+            let pos = self.board[nr][nf];
+            match pos {
+                Position::Empty => positions.push((nr, nf)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((nr, nf));
+                    }
+                    break;
+                }
+            }
+        }
+        for diff in 1..(BOARD_SIZE - rank).min(file + 1) {
+            let nr = rank + diff;
+            let nf = file - diff;
+            let pos = self.board[nr][nf];
+            match pos {
+                Position::Empty => positions.push((nr, nf)),
+                Position::Occupied(_, nc) => {
+                    if nc == colour.opposite() {
+                        positions.push((nr, nf));
+                    }
+                    break;
+                }
+            }
+        }
+        positions
+    }
+}
+
+/// Why a FEN string could not be parsed.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum FenParseError {
+    #[error("Insufficient parts, expected 6 but got {0}")]
+    InsufficientParts(usize),
+
+    #[error("Invalid colour to move: {0}")]
+    InvalidToMove(String),
+
+    #[error("Invalid castle character: {0}")]
+    InvalidCastleCharacter(char),
+
+    #[error("Invalid square len: {0}")]
+    InvalidSquareLen(usize),
+
+    #[error("Invalid file: {0}")]
+    InvalidFile(char),
+
+    #[error("Invalid rank: {0}")]
+    InvalidRank(char),
+
+    #[error("Number parse failed: {0}")]
+    InvalidNumber(String),
+
+    #[error("Insufficient ranks in position, expected 8 got {0}")]
+    InsufficientRanks(usize),
+
+    #[error("Invalid piece: {0}")]
+    InvalidPiece(char),
+
+    #[error("Rank {0} has more than 8 files")]
+    RankOverflow(usize),
+
+    #[error("Rank {0} has fewer than 8 files")]
+    RankUnderflow(usize),
+
+    #[error("Missing {0:?} king")]
+    MissingKing(Colour),
+
+    #[error("Expected one {0:?} king, got {1}")]
+    TooManyKings(Colour, usize),
+
+    #[error("Pawn on back rank: {0}")]
+    PawnOnBackRank(String),
+
+    #[error("Side not to move is in check")]
+    OpponentInCheck,
+
+    #[error("Inconsistent castling right: {0}")]
+    InconsistentCastling(char),
+
+    #[error("Invalid en-passant square: {0}")]
+    InvalidEnPassant(String),
+}
+
+/// Why a move could not be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum MoveParseError {
+    #[error("Invalid move syntax: {0}")]
+    InvalidSyntax(String),
+
+    #[error("Illegal move: {0}")]
+    Illegal(String),
+
+    #[error("Ambiguous move: {0}")]
+    Ambiguous(String),
+
+    #[error("Missing promotion piece: {0}")]
+    MissingPromotion(String),
+}
+
+impl FromStr for Fen {
+    type Err = FenParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(' ').collect();
+        if parts.len() != 6 {
+            return Err(FenParseError::InsufficientParts(parts.len()));
+        }
+
+        let board = parse_position(parts[0])?;
+
+        let to_move = if parts[1] == "w" {
+            Colour::White
+        } else if parts[1] == "b" {
+            Colour::Black
+        } else {
+            return Err(FenParseError::InvalidToMove(parts[1].to_string()));
+        };
+
+        let mut white_king_castle = false;
+        let mut white_queen_castle = false;
+        let mut black_king_castle = false;
+        let mut black_queen_castle = false;
+        if parts[2] != "-" {
+            for b in parts[2].bytes() {
+                if b == b'K' {
+                    white_king_castle = true;
+                } else if b == b'Q' {
+                    white_queen_castle = true;
+                } else if b == b'k' {
+                    black_king_castle = true;
+                } else if b == b'q' {
+                    black_queen_castle = true;
+                } else {
+                    return Err(FenParseError::InvalidCastleCharacter(b as char));
+                }
+            }
+        }
+
+        let en_passant = if parts[3] == "-" {
+            None
+        } else {
+            let sq = parse_square(parts[3])?;
+            Some(sq)
+        };
+
+        let halfmove_clock = parts[4]
+            .parse::<u32>()
+            .map_err(|e| FenParseError::InvalidNumber(e.to_string()))?;
+
+        let move_cnt = parts[5]
+            .parse::<u32>()
+            .map_err(|e| FenParseError::InvalidNumber(e.to_string()))?;
+
+        let mut fen = Fen {
+            board,
+            to_move,
+            white_king_castle,
+            white_queen_castle,
+            black_king_castle,
+            black_queen_castle,
+            en_passant,
+            halfmove_clock,
+            move_cnt,
+            hash: 0,
+        };
+        fen.validate()?;
+        fen.hash = fen.compute_hash();
+        Ok(fen)
+    }
+}
+
+impl Fen {
+    fn validate(&self) -> Result<(), FenParseError> {
+        for colour in enum_iterator::all::<Colour>() {
+            let king_cnt = self
+                .board
+                .iter()
+                .flatten()
+                .filter(|&&pos| pos == Position::Occupied(Piece::King, colour))
+                .count();
+            match king_cnt {
+                0 => return Err(FenParseError::MissingKing(colour)),
+                1 => {}
+                _ => return Err(FenParseError::TooManyKings(colour, king_cnt)),
+            }
+        }
+
+        for rank in [0, BOARD_SIZE - 1] {
+            for file in 0..BOARD_SIZE {
+                if matches!(self.board[rank][file], Position::Occupied(Piece::Pawn, _)) {
+                    let sq = format!("{}{}", file_to_char(file), rank_to_char(rank));
+                    return Err(FenParseError::PawnOnBackRank(sq));
+                }
+            }
+        }
+
+        if self
+            .board
+            .king_check_cnt()
+            .check_cnt(self.to_move.opposite())
+            != 0
+        {
+            return Err(FenParseError::OpponentInCheck);
+        }
+
+        let castles = [
+            (self.white_king_castle, 'K', Colour::White, 7, 7),
+            (self.white_queen_castle, 'Q', Colour::White, 7, 0),
+            (self.black_king_castle, 'k', Colour::Black, 0, 7),
+            (self.black_queen_castle, 'q', Colour::Black, 0, 0),
+        ];
+        for (can_castle, c, colour, rank, rook_file) in castles {
+            if can_castle
+                && (self.board[rank][4] != Position::Occupied(Piece::King, colour)
+                    || self.board[rank][rook_file] != Position::Occupied(Piece::Rook, colour))
+            {
+                return Err(FenParseError::InconsistentCastling(c));
+            }
+        }
+
+        if let Some((rank, file)) = self.en_passant {
+            // The pawn that just double-stepped must stand in front of the square
+            let (ep_rank, pawn_rank, from_rank) = match self.to_move {
+                Colour::White => (2, 3, 1),
+                Colour::Black => (5, 4, 6),
+            };
+            let pawn = Position::Occupied(Piece::Pawn, self.to_move.opposite());
+            if rank != ep_rank
+                || self.board[rank][file] != Position::Empty
+                || self.board[from_rank][file] != Position::Empty
+                || self.board[pawn_rank][file] != pawn
+            {
+                let sq = format!("{}{}", file_to_char(file), rank_to_char(rank));
+                return Err(FenParseError::InvalidEnPassant(sq));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Fen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (rank, rp) in self.board.iter().enumerate() {
+            if rank != 0 {
+                write!(f, "/")?;
+            }
+            let mut empty_cnt = 0;
+            for pos in rp {
+                let Position::Occupied(piece, colour) = *pos else {
+                    empty_cnt += 1;
+                    continue;
+                };
+                if empty_cnt != 0 {
+                    write!(f, "{}", empty_cnt)?;
+                    empty_cnt = 0;
+                }
+                write!(f, "{}", piece_to_fen_char(piece, colour))?;
+            }
+            if empty_cnt != 0 {
+                write!(f, "{}", empty_cnt)?;
+            }
+        }
+
+        let to_move = match self.to_move {
+            Colour::White => 'w',
+            Colour::Black => 'b',
+        };
+        write!(f, " {} ", to_move)?;
+
+        let castles = [
+            (self.white_king_castle, 'K'),
+            (self.white_queen_castle, 'Q'),
+            (self.black_king_castle, 'k'),
+            (self.black_queen_castle, 'q'),
+        ];
+        if castles.iter().all(|(can, _)| !can) {
+            write!(f, "-")?;
+        }
+        for (_, c) in castles.iter().filter(|(can, _)| *can) {
+            write!(f, "{}", c)?;
+        }
+
+        if let Some((rank, file)) = self.en_passant {
+            write!(f, " {}{}", file_to_char(file), rank_to_char(rank))?;
+        } else {
+            write!(f, " -")?;
+        }
+
+        write!(f, " {} {}", self.halfmove_clock, self.move_cnt)
+    }
+}
+
+fn piece_to_fen_char(piece: Piece, colour: Colour) -> char {
+    let c = piece.to_char().unwrap_or('P');
+    match colour {
+        Colour::White => c,
+        Colour::Black => c.to_ascii_lowercase(),
+    }
+}
+
+fn parse_square(s: &str) -> Result<(usize, usize), FenParseError> {
+    if s.len() != 2 {
+        return Err(FenParseError::InvalidSquareLen(s.len()));
+    }
+    let sb = s.as_bytes();
+    let file = sb[0];
+    let rank = sb[1];
+    if !(b'a'..=b'h').contains(&file) {
+        return Err(FenParseError::InvalidFile(file as char));
+    }
+    if !(b'1'..=b'8').contains(&rank) {
+        return Err(FenParseError::InvalidRank(rank as char));
+    }
+    Ok(((8 - (rank - b'0')) as usize, (file - b'a') as usize))
+}
+
+fn parse_position(position: &str) -> Result<Board, FenParseError> {
+    let mut board = [[Position::Empty; BOARD_SIZE]; BOARD_SIZE];
+    let ranks: Vec<_> = position.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenParseError::InsufficientRanks(ranks.len()));
+    }
+    for (rank, rank_str) in ranks.into_iter().enumerate() {
+        let mut file = 0;
+        for b in rank_str.bytes() {
+            if file >= BOARD_SIZE {
+                return Err(FenParseError::RankOverflow(BOARD_SIZE - rank));
+            }
+            match b {
+                b'p' => {
+                    board[rank][file] = Position::Occupied(Piece::Pawn, Colour::Black);
+                    file += 1;
+                }
+                b'P' => {
+                    board[rank][file] = Position::Occupied(Piece::Pawn, Colour::White);
+                    file += 1;
+                }
+                b'r' => {
+                    board[rank][file] = Position::Occupied(Piece::Rook, Colour::Black);
+                    file += 1;
+                }
+                b'R' => {
+                    board[rank][file] = Position::Occupied(Piece::Rook, Colour::White);
+                    file += 1;
+                }
+                b'n' => {
+                    board[rank][file] = Position::Occupied(Piece::Knight, Colour::Black);
+                    file += 1;
+                }
+                b'N' => {
+                    board[rank][file] = Position::Occupied(Piece::Knight, Colour::White);
+                    file += 1;
+                }
+                b'b' => {
+                    board[rank][file] = Position::Occupied(Piece::Bishop, Colour::Black);
+                    file += 1;
+                }
+                b'B' => {
+                    board[rank][file] = Position::Occupied(Piece::Bishop, Colour::White);
+                    file += 1;
+                }
+                b'k' => {
+                    board[rank][file] = Position::Occupied(Piece::King, Colour::Black);
+                    file += 1;
+                }
+                b'K' => {
+                    board[rank][file] = Position::Occupied(Piece::King, Colour::White);
+                    file += 1;
+                }
+                b'q' => {
+                    board[rank][file] = Position::Occupied(Piece::Queen, Colour::Black);
+                    file += 1;
+                }
+                b'Q' => {
+                    board[rank][file] = Position::Occupied(Piece::Queen, Colour::White);
+                    file += 1;
+                }
+                b'1'..=b'8' => file += (b - b'0') as usize,
+                _ => return Err(FenParseError::InvalidPiece(b as char)),
+            }
+        }
+        if file > BOARD_SIZE {
+            return Err(FenParseError::RankOverflow(BOARD_SIZE - rank));
+        } else if file < BOARD_SIZE {
+            return Err(FenParseError::RankUnderflow(BOARD_SIZE - rank));
+        }
+    }
+    Ok(Board(board))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use proptest::sample::{select, Index};
+
+    use super::*;
+
+    fn find_move(fen: &Fen, uci: &str) -> Move {
+        fen.parse_uci(uci)
+            .unwrap_or_else(|e| panic!("{} in {}", e, fen))
+    }
+
+    #[test]
+    fn san_table() {
+        const TABLE: &[(&str, &str, &str)] = &[
+            // Plain pawn and piece moves
+            (INIT_FEN, "e2e4", "e4"),
+            (INIT_FEN, "g1f3", "Nf3"),
+            (
+                "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+                "e4d5",
+                "exd5",
+            ),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+            // File, rank and full square disambiguation
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2", "Nbd2"),
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "f1d2", "Nfd2"),
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"),
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a5a3", "R5a3"),
+            ("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1", "Qh4e1"),
+            ("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "e4e1", "Qee1"),
+            ("2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h1e1", "Q1e1"),
+            // Pinned pieces do not need disambiguating against
+            ("4k3/4r3/8/8/8/8/4N3/1N2K3 w - - 0 1", "b1c3", "Nc3"),
+            // Promotions
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q", "a8=Q+"),
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8r", "a8=R+"),
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8b", "a8=B"),
+            ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8n", "a8=N"),
+            ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q", "axb8=Q+"),
+            ("4k3/8/8/8/8/8/p7/4K3 b - - 0 1", "a2a1q", "a1=Q+"),
+            // Disambiguation between a promoted piece and an original one
+            ("Q7/8/7k/8/8/8/8/Q3K3 w - - 0 1", "a8a4", "Q8a4"),
+            // Castling
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1", "O-O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8g8", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", "O-O-O"),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1c1", "O-O-O"),
+            // Checks and mates
+            ("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1", "e4d6", "Nd6+"),
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+                "d8h4",
+                "Qh4#",
+            ),
+            ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", "e1c1", "O-O-O+"),
+            ("3k4/8/3K4/8/8/8/8/R7 w - - 0 1", "a1a8", "Ra8#"),
+        ];
+        for &(fen_str, uci, san) in TABLE {
+            let fen = fen_str.parse::<Fen>().unwrap();
+            let mov = find_move(&fen, uci);
+            assert_eq!(fen.move_string(mov), san, "{} in {}", uci, fen_str);
+        }
+    }
+
+    #[test]
+    fn san_round_trip() {
+        const FENS: &[&str] = &[
+            INIT_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "2k5/8/8/8/4Q2Q/8/8/K6Q w - - 0 1",
+            "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ];
+        for fen_str in FENS {
+            let fen = fen_str.parse::<Fen>().unwrap();
+            for mov in fen.generate_moves() {
+                let san = fen.move_string(mov);
+                assert_eq!(fen.parse_san(&san).unwrap(), mov, "{} in {}", san, fen_str);
+            }
+        }
+    }
+
+    #[test]
+    fn notation_round_trip() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"
+            .parse::<Fen>()
+            .unwrap();
+        for mov in fen.generate_moves() {
+            for notation in [Notation::San, Notation::Lan, Notation::Uci] {
+                let text = fen.notation(mov, notation);
+                assert_eq!(fen.parse_move(&text).unwrap(), mov, "{}", text);
+            }
+        }
+        let fen = fen.apply_move(find_move(&fen, "g1h1"));
+        let mov = find_move(&fen, "b2a1q");
+        assert_eq!(fen.notation(mov, Notation::Lan), "b2xa1=Q");
+        assert_eq!(fen.notation(mov, Notation::Uci), "b2a1q");
+        assert!(matches!(
+            fen.parse_move("Nb7-a5"),
+            Err(MoveParseError::InvalidSyntax(_))
+        ));
+    }
+
+    #[test]
+    fn parse_san_variants() {
+        let fen = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1".parse::<Fen>().unwrap();
+        assert_eq!(fen.parse_san("axb8Q").unwrap(), find_move(&fen, "a7b8q"));
+        assert_eq!(
+            fen.parse_san("a7xb8=Q+!?").unwrap(),
+            find_move(&fen, "a7b8q")
+        );
+        for san in ["a8", "axb8", "a8+"] {
+            assert!(matches!(
+                fen.parse_san(san),
+                Err(MoveParseError::MissingPromotion(_))
+            ));
+        }
+        assert!(matches!(
+            fen.parse_san("Ke1"),
+            Err(MoveParseError::Illegal(_))
+        ));
+        assert!(matches!(
+            fen.parse_san("Kz9"),
+            Err(MoveParseError::InvalidSyntax(_))
+        ));
+        assert!(matches!(
+            fen.parse_san("O-O"),
+            Err(MoveParseError::Illegal(_))
+        ));
+
+        let fen = "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1".parse::<Fen>().unwrap();
+        assert!(matches!(
+            fen.parse_san("Nd2"),
+            Err(MoveParseError::Ambiguous(_))
+        ));
+        assert_eq!(fen.parse_san("Nb1d2").unwrap(), find_move(&fen, "b1d2"));
+        assert_eq!(fen.parse_san("0-0").ok(), None);
+    }
+
+    fn live_nodes(game: &Game) -> usize {
+        game.tree.store.len()
+    }
+
+    fn play(game: &mut Game, sans: &[&str]) {
+        for san in sans {
+            game.apply_move(game.parse_san(san).unwrap());
+        }
+    }
+
+    #[test]
+    fn variation_editing() {
+        let pgn = "1. e4 e5 (1... c5 2. Nf3 (2. Nc3) d6) (1... e6) 2. Nf3 *";
+        let mut game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(live_nodes(&game), 9);
+
+        // Delete 1... c5 and everything after it
+        play(&mut game, &["e4", "c5"]);
+        game.delete_variation();
+        assert_eq!(
+            game.current_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(live_nodes(&game), 5);
+        assert!(game.to_pgn().ends_with("1. e4 e5 (1... e6) 2. Nf3 *\n"));
+
+        play(&mut game, &["e6"]);
+        game.reorder_variation(true);
+        assert!(game.to_pgn().ends_with("1. e4 e5 (1... e6) 2. Nf3 *\n"));
+        game.promote_variation_to_mainline();
+        assert!(game.to_pgn().ends_with("1. e4 e6 (1... e5 2. Nf3) *\n"));
+        game.reorder_variation(false);
+        assert!(game.to_pgn().ends_with("1. e4 e6 (1... e5 2. Nf3) *\n"));
+
+        // Truncating frees the nodes after the current one
+        game.back();
+        game.truncate_after_current();
+        assert_eq!(live_nodes(&game), 2);
+        assert!(game.to_pgn().ends_with("1. e4 *\n"));
+        play(&mut game, &["d5", "exd5", "Qxd5"]);
+        assert_eq!(live_nodes(&game), 5);
+        assert!(game.to_pgn().ends_with("1. e4 d5 2. exd5 Qxd5 *\n"));
+    }
+
+    #[test]
+    fn node_navigation() {
+        let mut game = Game::from_pgn("1. e4 e5 (1... c5 2. Nf3) 2. Nf3 Nc6 *").unwrap();
+        let main: Vec<_> = game.game_moves().iter().map(|gm| gm.node).collect();
+        assert_eq!(main.len(), 4);
+        assert!(game.path_to_current().is_empty());
+
+        game.goto_end();
+        assert_eq!(game.current(), main[3]);
+        assert!(game.game_moves()[3].is_curr);
+        assert_eq!(game.path_to_current(), main);
+
+        let (c5_mov, c5) = game.children(main[0]).unwrap()[1];
+        assert_eq!(game.parent(c5), Some(main[0]));
+        assert!(game.goto(c5));
+        assert_eq!(c5_mov.to(), (3, 2));
+        game.goto_end();
+        let nf3 = game.current();
+        assert_eq!(game.path_to_current(), vec![main[0], c5, nf3]);
+        assert_eq!(
+            game.current_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        // Handles to deleted nodes are rejected, even once their slots are reused
+        game.goto(c5);
+        game.delete_variation();
+        assert_eq!(game.current(), main[0]);
+        play(&mut game, &["d5", "exd5"]);
+        assert!(!game.goto(c5));
+        assert!(!game.goto(nf3));
+        assert!(game.children(nf3).is_none());
+        assert!(game.goto(main[1]));
+
+        game.goto_start();
+        assert_eq!(game.current(), game.root());
+    }
+
+    #[test]
+    fn continuations() {
+        let mut game = Game::from_pgn("1. e4 e5 (1... c5) (1... e6) 2. Nf3 *").unwrap();
+        let e4 = game.game_moves()[0].node;
+        let notation = |game: &Game| -> Vec<_> {
+            let moves = game.continuations(e4);
+            moves
+                .into_iter()
+                .map(|gm| (gm.move_num, gm.notation))
+                .collect()
+        };
+        assert_eq!(
+            notation(&game),
+            [
+                (1, "e5".to_string()),
+                (1, "c5".to_string()),
+                (1, "e6".to_string())
+            ]
+        );
+
+        // The line followed forward comes first, the rest keep their order
+        game.goto(e4);
+        game.forward();
+        game.next_variation();
+        assert_eq!(
+            notation(&game),
+            [
+                (1, "c5".to_string()),
+                (1, "e5".to_string()),
+                (1, "e6".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn castling_rules() {
+        fn castles(fen: &Fen) -> Vec<Vec<String>> {
+            [MoveGen::Mailbox, MoveGen::Bitboard]
+                .into_iter()
+                .map(|gen| {
+                    let mut moves: Vec<_> = fen
+                        .generate_moves_with(gen)
+                        .into_iter()
+                        .filter(|mov| mov.is_king_castle() || mov.is_queen_castle())
+                        .map(|mov| mov.uci())
+                        .collect();
+                    moves.sort();
+                    moves
+                })
+                .collect()
+        }
+        const TABLE: &[(&str, &[&str])] = &[
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &["e1c1", "e1g1"]),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", &["e8c8", "e8g8"]),
+            // Not out of check
+            ("4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1", &[]),
+            // Not through an attacked square
+            ("4k3/5r2/8/8/8/8/8/R3K2R w KQ - 0 1", &["e1c1"]),
+            ("4k3/3r4/8/8/8/8/8/R3K2R w KQ - 0 1", &["e1g1"]),
+            ("r3k2r/8/8/8/8/8/8/4KR2 b kq - 0 1", &["e8c8"]),
+            // Not into check
+            ("4k3/6r1/8/8/8/8/8/R3K2R w KQ - 0 1", &["e1c1"]),
+            ("4k3/2r5/8/8/8/8/8/R3K2R w KQ - 0 1", &["e1g1"]),
+            // The rook may pass an attacked square
+            ("4k3/1r6/8/8/8/8/8/R3K2R w KQ - 0 1", &["e1c1", "e1g1"]),
+            // Not through pieces
+            ("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1", &["e1g1"]),
+            ("4k3/8/8/8/8/8/8/R3K1nR w KQ - 0 1", &["e1c1"]),
+            // Not without the right
+            ("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1", &["e1c1"]),
+        ];
+        for &(fen, want) in TABLE {
+            let fen = fen.parse::<Fen>().unwrap();
+            for got in castles(&fen) {
+                assert_eq!(got, want, "{}", fen);
+            }
+        }
+
+        // A rook that is taken, or moves away and back, cannot castle
+        let fen = "4k3/8/8/8/8/8/6b1/R3K2R b KQ - 0 1".parse::<Fen>().unwrap();
+        let fen = fen.apply_move(find_move(&fen, "g2h1"));
+        assert_eq!(castles(&fen), [["e1c1"], ["e1c1"]]);
+        let mut fen = "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1".parse::<Fen>().unwrap();
+        for uci in ["a1a2", "e8d8", "a2a1", "d8e8"] {
+            fen = fen.apply_move(find_move(&fen, uci));
+        }
+        assert_eq!(castles(&fen), [["e1g1"], ["e1g1"]]);
+    }
+
+    #[test]
+    fn termination_table() {
+        use GameResult::*;
+        use Termination::*;
+        const TABLE: &[(&str, Option<(GameResult, Termination)>)] = &[
+            (INIT_FEN, None),
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                Some((BlackWins, Checkmate)),
+            ),
+            (
+                "R5k1/5ppp/8/8/8/8/8/4K3 b - - 1 1",
+                Some((WhiteWins, Checkmate)),
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Some((Draw, Stalemate))),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 99 80", None),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 100 80",
+                Some((Draw, FiftyMoveRule)),
+            ),
+            // Mate on the last move before the fifty-move rule still counts
+            (
+                "R5k1/5ppp/8/8/8/8/8/4K3 b - - 100 80",
+                Some((WhiteWins, Checkmate)),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                Some((Draw, InsufficientMaterial)),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/3NK3 w - - 0 1",
+                Some((Draw, InsufficientMaterial)),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+                Some((Draw, InsufficientMaterial)),
+            ),
+            // Bishops all on one square colour, on either side
+            (
+                "4k3/8/8/8/8/4B3/8/2B1K3 w - - 0 1",
+                Some((Draw, InsufficientMaterial)),
+            ),
+            (
+                "2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+                Some((Draw, InsufficientMaterial)),
+            ),
+            ("2b1k3/8/8/8/8/4B3/8/2B1KB2 w - - 0 1", None),
+            ("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", None),
+            ("4k3/8/8/8/8/8/8/2N1KB2 w - - 0 1", None),
+            ("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1", None),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", None),
+        ];
+        for &(fen, want) in TABLE {
+            let want = match want {
+                Some((result, termination)) => GameStatus::Over(result, termination),
+                None => GameStatus::Ongoing,
+            };
+            assert_eq!(Game::from_fen(fen).unwrap().status(), want, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut game = Game::new();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        play(&mut game, &shuffle);
+        play(&mut game, &shuffle[..3]);
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        play(&mut game, &shuffle[3..]);
+        assert_eq!(
+            game.status(),
+            GameStatus::Over(GameResult::Draw, Termination::ThreefoldRepetition)
+        );
+    }
+
+    #[test]
+    fn no_moves_after_game_over() {
+        let e4 = find_move(&INIT_FEN.parse().unwrap(), "e2e4");
+        let mut game = Game::new();
+        play(&mut game, &["f3", "e5", "g4", "Qh4#"]);
+        let over = [
+            game,
+            Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap(),
+            Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").unwrap(),
+        ];
+        for mut game in over {
+            assert!(game.status().is_over());
+            let (node, fen) = (game.current(), game.current_fen());
+            let mov = game.all_legal_moves().first().copied().unwrap_or(e4);
+            game.apply_move(mov);
+            assert_eq!((game.current(), game.current_fen()), (node, fen));
+            assert!(game.children(node).unwrap().is_empty());
+        }
+
+        // Nor can a PGN carry on past mate
+        let err = Game::from_pgn("1. f3 e5 2. g4 Qh4# 3. a3 *").unwrap_err();
+        assert_eq!((err.line, err.col), (1, 24));
+    }
+
+    #[test]
+    fn fen_errors() {
+        use FenParseError::*;
+        let table = [
+            ("8/8/8/8/8/8/8/8 w - -", InsufficientParts(4)),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                InvalidToMove("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w Kx - 0 1",
+                InvalidCastleCharacter('x'),
+            ),
+            ("4k3/8/8/8/8/8/8/4K3 w - e 0 1", InvalidSquareLen(1)),
+            ("4k3/8/8/8/8/8/8/4K3 w - i6 0 1", InvalidFile('i')),
+            ("4k3/8/8/8/8/8/8/4K3 w - e9 0 1", InvalidRank('9')),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                InvalidNumber("invalid digit found in string".to_string()),
+            ),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", InsufficientRanks(7)),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", InvalidPiece('X')),
+            ("4k3/8/8/8/8/8/8/K9 w - - 0 1", InvalidPiece('9')),
+            ("4k3p/8/8/8/8/8/8/4K3 w - - 0 1", RankOverflow(8)),
+            ("4k3/8/8/8/8/8/8/4K4 w - - 0 1", RankOverflow(1)),
+            ("4k2/8/8/8/8/8/8/4K3 w - - 0 1", RankUnderflow(8)),
+            ("4k3/8/8/8/8/8/8/8 w - - 0 1", MissingKing(Colour::White)),
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", MissingKing(Colour::Black)),
+            // With an en-passant square to check, which needs the king
+            (
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQpBNR w - f6 0 3",
+                MissingKing(Colour::White),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+                TooManyKings(Colour::White, 2),
+            ),
+            (
+                "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                PawnOnBackRank("a8".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/p3K3 w - - 0 1",
+                PawnOnBackRank("a1".to_string()),
+            ),
+            ("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1", OpponentInCheck),
+            ("4k3/8/8/8/8/8/8/4K3 w K - 0 1", InconsistentCastling('K')),
+            ("4k2r/8/8/8/8/8/8/4K3 w q - 0 1", InconsistentCastling('q')),
+            (
+                "r3k2r/8/8/8/8/8/8/R4K1R w Kkq - 0 1",
+                InconsistentCastling('K'),
+            ),
+            (
+                "4k3/8/8/8/4P3/8/8/4K3 b - e4 0 1",
+                InvalidEnPassant("e4".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 b - e3 0 1",
+                InvalidEnPassant("e3".to_string()),
+            ),
+            (
+                "4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1",
+                InvalidEnPassant("e3".to_string()),
+            ),
+            (
+                "4k3/8/8/8/4P3/8/4P3/4K3 b - e3 0 1",
+                InvalidEnPassant("e3".to_string()),
+            ),
+        ];
+        for (fen, err) in table {
+            assert_eq!(fen.parse::<Fen>().unwrap_err(), err, "{}", fen);
+            assert_eq!(Game::from_fen(fen).unwrap_err(), err, "{}", fen);
+        }
+        assert!("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1".parse::<Fen>().is_ok());
+    }
+
+    #[test]
+    fn incremental_hash() {
+        fn walk(fen: &Fen, depth: usize) {
+            assert_eq!(fen.hash, fen.compute_hash(), "{}", fen);
+            if depth == 0 {
+                return;
+            }
+            for mov in fen.generate_moves() {
+                walk(&fen.apply_move(mov), depth - 1);
+            }
+        }
+        for fen in [
+            INIT_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            walk(&fen.parse().unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn transpositions() {
+        let pgn = "1. e4 e5 2. Nf3 (2. Nc3 Nc6 3. Nf3) Nc6 3. Nc3 Nf6 *";
+        let mut game = Game::from_pgn(pgn).unwrap();
+        assert!(game.transpositions().is_empty());
+        let main = game.game_moves();
+        game.goto(main[4].node);
+        let found = game.transpositions();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].move_num, found[0].notation.as_str()), (3, "Nf3"));
+
+        game.goto(found[0].node);
+        assert_eq!(game.transpositions()[0].node, main[4].node);
+        game.delete_variation();
+        game.goto(main[4].node);
+        assert!(game.transpositions().is_empty());
+        game.goto(main[5].node);
+        assert!(game.transpositions().is_empty());
+    }
+
+    #[test]
+    fn en_passant_hashing() {
+        // Only an en-passant square a pawn can use sets a position apart
+        for (fen, distinct) in [
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+                false,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+                true,
+            ),
+            ("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1", true),
+            // Taking would leave the king in check from the rook
+            ("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1", false),
+        ] {
+            let with = fen.parse::<Fen>().unwrap();
+            let mut without = with;
+            without.en_passant = None;
+            without.hash = without.compute_hash();
+            assert_eq!(with.hash != without.hash, distinct, "{}", fen);
+            assert_eq!(!with.is_same_position(&without), distinct, "{}", fen);
+        }
+
+        // 1. e4 e5 leaves an en-passant square no pawn can use, so the same
+        // position reached by single steps is a transposition
+        let mut game = Game::from_pgn("1. e4 (1. e3 e6 2. e4 e5) e5 *").unwrap();
+        let main = game.game_moves();
+        game.goto(main[1].node);
+        let found = game.transpositions();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].move_num, found[0].notation.as_str()), (2, "e5"));
+
+        // and counts towards repetition
+        let mut game = Game::new();
+        play(&mut game, &["e4", "e5", "Nf3", "Nf6", "Ng1", "Ng8"]);
+        play(&mut game, &["Nf3", "Nf6", "Ng1"]);
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        play(&mut game, &["Ng8"]);
+        assert_eq!(
+            game.status(),
+            GameStatus::Over(GameResult::Draw, Termination::ThreefoldRepetition)
+        );
+    }
+
+    #[test]
+    fn parse_uci() {
+        let fen = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1".parse::<Fen>().unwrap();
+        let mov = fen.parse_uci("a7b8n").unwrap();
+        assert_eq!(
+            (mov.from(), mov.to(), mov.promotion()),
+            ((1, 0), (0, 1), Some(Piece::Knight))
+        );
+        assert!(matches!(
+            fen.parse_uci("a7b8"),
+            Err(MoveParseError::Illegal(_))
+        ));
+        assert!(matches!(
+            fen.parse_uci("a7b8k"),
+            Err(MoveParseError::InvalidSyntax(_))
+        ));
+        assert!(matches!(
+            fen.parse_uci("e1e3"),
+            Err(MoveParseError::Illegal(_))
+        ));
+        assert!(matches!(
+            fen.parse_uci("e1"),
+            Err(MoveParseError::InvalidSyntax(_))
+        ));
+    }
+
+    proptest! {
+        // Random games from a few starting points, taken back one move at a
+        // time, must pass through exactly the positions they went through
+        #[test]
+        fn make_unmake_round_trip(
+            start in select(&[
+                INIT_FEN,
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            ][..]),
+            choices in prop::collection::vec(any::<Index>(), 0..60),
+        ) {
+            let mut fen = start.parse::<Fen>().unwrap();
+            let mut history = Vec::new();
+            for choice in choices {
+                let moves = fen.generate_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                let mov = *choice.get(&moves);
+                let before = (fen.to_string(), fen.board, fen.hash);
+                let undo = fen.make_move(mov);
+                prop_assert_eq!(fen.hash, fen.compute_hash());
+                history.push((before, undo));
+            }
+            while let Some(((fen_str, board, hash), undo)) = history.pop() {
+                fen.unmake_move(undo);
+                prop_assert_eq!(fen.to_string(), fen_str);
+                prop_assert_eq!(fen.board, board);
+                prop_assert_eq!(fen.hash, hash);
+            }
+            prop_assert_eq!(fen.to_string(), start.parse::<Fen>().unwrap().to_string());
+        }
+
+        // Any string of FEN characters is either refused or gives a position
+        // that can be played from
+        #[test]
+        fn fen_never_panics(fen in "[pnbrqkPNBRQK0-9/ wb\\-a-h]{0,80}") {
+            if let Ok(game) = Game::from_fen(&fen) {
+                game.all_legal_moves();
+                prop_assert!(game.current_fen().parse::<Fen>().is_ok());
+            }
+        }
+
+        // As above, for valid FENs with a few characters changed
+        #[test]
+        fn mangled_fen_never_panics(
+            start in select(&[
+                INIT_FEN,
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            ][..]),
+            edits in prop::collection::vec(
+                (any::<Index>(), select(&b"pnbrqkPNBRQK0189/ wb-aeh"[..])),
+                1..4,
+            ),
+        ) {
+            let mut fen = start.as_bytes().to_vec();
+            for (idx, b) in edits {
+                let i = idx.index(fen.len());
+                fen[i] = b;
+            }
+            let fen = String::from_utf8(fen).unwrap();
+            if let Ok(game) = Game::from_fen(&fen) {
+                game.all_legal_moves();
+                prop_assert!(game.current_fen().parse::<Fen>().is_ok());
+            }
+        }
+    }
+}
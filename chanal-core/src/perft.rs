@@ -0,0 +1,134 @@
+use super::{Fen, Move, MoveGen};
+
+impl Fen {
+    /// Number of leaf nodes of the legal move tree `depth` plies deep
+    pub fn perft(&self, depth: u32) -> u64 {
+        self.perft_with(depth, MoveGen::Bitboard)
+    }
+
+    /// Perft using the given move generator.
+    pub fn perft_with(&self, depth: u32, gen: MoveGen) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.generate_moves_with(gen);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut fen = *self;
+        moves
+            .into_iter()
+            .map(|mov| {
+                let undo = fen.make_move(mov);
+                let cnt = fen.perft_with(depth - 1, gen);
+                fen.unmake_move(undo);
+                cnt
+            })
+            .sum()
+    }
+
+    /// Perft split up by the first move, for tracking down generator bugs
+    pub fn divide(&self, depth: u32, gen: MoveGen) -> Vec<(Move, u64)> {
+        let mut moves: Vec<_> = self
+            .generate_moves_with(gen)
+            .into_iter()
+            .map(|mov| {
+                let cnt = self
+                    .apply_move(mov)
+                    .perft_with(depth.saturating_sub(1), gen);
+                (mov, cnt)
+            })
+            .collect();
+        moves.sort_by_key(|(mov, _)| mov.uci());
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Piece, INIT_FEN};
+
+    // Both generators must give the known counts
+    fn check(fen: &str, counts: &[u64]) {
+        let fen = fen.parse::<Fen>().unwrap();
+        for gen in [MoveGen::Bitboard, MoveGen::Mailbox] {
+            for (depth, &count) in (1..).zip(counts) {
+                let nodes = fen.perft_with(depth, gen);
+                assert_eq!(nodes, count, "{:?} depth {} of {}", gen, depth, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn initial() {
+        check(INIT_FEN, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn position_3() {
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    #[test]
+    fn position_4() {
+        check(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn position_5() {
+        check(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        );
+    }
+
+    #[test]
+    fn position_6() {
+        check(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        );
+    }
+
+    #[test]
+    fn divide() {
+        let fen = INIT_FEN.parse::<Fen>().unwrap();
+        for gen in [MoveGen::Bitboard, MoveGen::Mailbox] {
+            let divide = fen.divide(3, gen);
+            assert_eq!(divide.len(), 20);
+            assert_eq!(divide.iter().map(|(_, n)| n).sum::<u64>(), 8902);
+            assert_eq!(divide[0].0.uci(), "a2a3");
+            assert_eq!(divide[0].1, 380);
+        }
+    }
+
+    #[test]
+    fn black_en_passant() {
+        let fen = "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1".parse::<Fen>().unwrap();
+        let mov = fen.parse_uci("d4e3").unwrap();
+        let cap = mov.capture.unwrap();
+        assert_eq!(
+            (cap.piece, cap.colour, cap.pos),
+            (Piece::Pawn, Colour::White, (4, 4))
+        );
+        let mut board = fen.board;
+        board.apply_move(mov);
+        board.unapply_move(mov);
+        assert_eq!(board, fen.board);
+    }
+}
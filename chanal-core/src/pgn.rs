@@ -0,0 +1,817 @@
+use std::fmt::{self, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::{
+    arena::NodeId, Colour, Fen, FenParseError, FenTree, Game, GameStatus, Move, MoveParseError,
+    Nag, Notation, INIT_FEN,
+};
+
+/// A PGN parse error, and the line and column (from 1) it was found at.
+#[derive(Debug, thiserror::Error)]
+#[error("{line}:{col}: {kind}")]
+pub struct PgnError {
+    /// Line of the error.
+    pub line: usize,
+    /// Column of the error.
+    pub col: usize,
+    /// What went wrong.
+    pub kind: PgnErrorKind,
+}
+
+/// The kinds of PGN parse error.
+#[derive(Debug, thiserror::Error)]
+pub enum PgnErrorKind {
+    #[error("Unexpected character: {0}")]
+    UnexpectedChar(char),
+
+    #[error("Unterminated string")]
+    UnterminatedString,
+
+    #[error("Unterminated comment")]
+    UnterminatedComment,
+
+    #[error("Unexpected token: {0}")]
+    UnexpectedToken(String),
+
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+
+    #[error("Variation before any move")]
+    VariationWithoutMove,
+
+    #[error("No game found")]
+    NoGame,
+
+    #[error(transparent)]
+    Move(#[from] MoveParseError),
+
+    #[error("Invalid FEN tag: {0}")]
+    Fen(#[from] FenParseError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    TagStart,
+    TagEnd,
+    Str(String),
+    Symbol(String),
+    Period,
+    Nag(u8),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::TagStart => write!(f, "["),
+            Token::TagEnd => write!(f, "]"),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Symbol(s) | Token::Result(s) => write!(f, "{}", s),
+            Token::Period => write!(f, "."),
+            Token::Nag(n) => write!(f, "${}", n),
+            Token::Comment(c) => write!(f, "{{{}}}", c),
+            Token::VariationStart => write!(f, "("),
+            Token::VariationEnd => write!(f, ")"),
+        }
+    }
+}
+
+// A token along with the line and column it starts at
+type Spanned = (Token, usize, usize);
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(pgn: &'a str) -> Self {
+        Self {
+            chars: pgn.chars().peekable(),
+            line: 1,
+            col: 1,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, line: usize, col: usize, kind: PgnErrorKind) -> PgnError {
+        PgnError { line, col, kind }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Spanned>, PgnError> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+                // Escape mechanism: lines starting with % are ignored
+                Some('%') if self.col == 1 => self.skip_line(),
+                Some(';') => self.skip_line(),
+                _ => break,
+            }
+        }
+
+        let (line, col) = (self.line, self.col);
+        let Some(c) = self.bump() else {
+            return Ok(None);
+        };
+        let token = match c {
+            '[' => Token::TagStart,
+            ']' => Token::TagEnd,
+            '(' => Token::VariationStart,
+            ')' => Token::VariationEnd,
+            '.' => Token::Period,
+            '*' => Token::Result("*".to_string()),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => break,
+                        Some('\\') => match self.bump() {
+                            Some(c) => s.push(c),
+                            None => {
+                                return Err(self.error(line, col, PgnErrorKind::UnterminatedString))
+                            }
+                        },
+                        Some(c) => s.push(c),
+                        None => {
+                            return Err(self.error(line, col, PgnErrorKind::UnterminatedString))
+                        }
+                    }
+                }
+                Token::Str(s)
+            }
+            '{' => {
+                let mut s = String::new();
+                loop {
+                    match self.bump() {
+                        Some('}') => break,
+                        Some(c) => s.push(c),
+                        None => {
+                            return Err(self.error(line, col, PgnErrorKind::UnterminatedComment))
+                        }
+                    }
+                }
+                Token::Comment(s.trim().to_string())
+            }
+            '$' => {
+                let mut s = String::new();
+                while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_digit()) {
+                    s.push(c);
+                    self.bump();
+                }
+                let nag = s
+                    .parse::<u8>()
+                    .map_err(|_| self.error(line, col, PgnErrorKind::UnexpectedChar('$')))?;
+                Token::Nag(nag)
+            }
+            '!' | '?' => {
+                let mut s = c.to_string();
+                while let Some(&c) = self.chars.peek().filter(|&&c| c == '!' || c == '?') {
+                    s.push(c);
+                    self.bump();
+                }
+                match Nag::from_glyph(&s) {
+                    Some(nag) => Token::Nag(nag.0),
+                    None => return Err(self.error(line, col, PgnErrorKind::UnexpectedToken(s))),
+                }
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let mut s = c.to_string();
+                while let Some(&c) = self
+                    .chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || "_+#=:-/".contains(**c))
+                {
+                    s.push(c);
+                    self.bump();
+                }
+                if matches!(s.as_str(), "1-0" | "0-1" | "1/2-1/2") {
+                    Token::Result(s)
+                } else {
+                    Token::Symbol(s)
+                }
+            }
+            c => return Err(self.error(line, col, PgnErrorKind::UnexpectedChar(c))),
+        };
+        Ok(Some((token, line, col)))
+    }
+}
+
+struct PgnParser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Spanned>,
+    // Whether the tag section is being read, for skip_to_next_game
+    in_tags: bool,
+}
+
+impl<'a> PgnParser<'a> {
+    fn new(pgn: &'a str) -> Self {
+        Self {
+            lexer: Lexer::new(pgn),
+            peeked: None,
+            in_tags: false,
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Spanned>, PgnError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<Spanned>, PgnError> {
+        match self.peeked.take() {
+            Some(spanned) => Ok(Some(spanned)),
+            None => self.lexer.next_token(),
+        }
+    }
+
+    fn unexpected(&self, spanned: Option<Spanned>) -> PgnError {
+        match spanned {
+            Some((token, line, col)) => PgnError {
+                line,
+                col,
+                kind: PgnErrorKind::UnexpectedToken(token.to_string()),
+            },
+            None => PgnError {
+                line: self.lexer.line,
+                col: self.lexer.col,
+                kind: PgnErrorKind::UnexpectedEof,
+            },
+        }
+    }
+
+    fn next_game(&mut self) -> Result<Option<Game>, PgnError> {
+        let Some(&(_, line, col)) = self.peek()? else {
+            return Ok(None);
+        };
+
+        let tags = self.parse_tags()?;
+        let fen = tags
+            .iter()
+            .find(|(name, _)| name == "FEN")
+            .map_or(INIT_FEN, |(_, value)| value.as_str())
+            .parse::<Fen>()
+            .map_err(|e| PgnError {
+                line,
+                col,
+                kind: e.into(),
+            })?;
+        let mut game = Game {
+            tree: FenTree::new(fen),
+            tags,
+        };
+
+        let root = game.tree.root;
+        if let Some(result) = self.parse_line(&mut game.tree, root, 0)? {
+            match game.tags.iter_mut().find(|(name, _)| name == "Result") {
+                Some((_, value)) => *value = result,
+                None => game.tags.push(("Result".to_string(), result)),
+            }
+        }
+        Ok(Some(game))
+    }
+
+    fn parse_tags(&mut self) -> Result<Vec<(String, String)>, PgnError> {
+        self.in_tags = true;
+        let mut tags = Vec::new();
+        while matches!(self.peek()?, Some((Token::TagStart, _, _))) {
+            self.next()?;
+            let name = match self.next()? {
+                Some((Token::Symbol(name), _, _)) => name,
+                other => return Err(self.unexpected(other)),
+            };
+            let value = match self.next()? {
+                Some((Token::Str(value), _, _)) => value,
+                other => return Err(self.unexpected(other)),
+            };
+            match self.next()? {
+                Some((Token::TagEnd, _, _)) => {}
+                other => return Err(self.unexpected(other)),
+            }
+            tags.push((name, value));
+        }
+        self.in_tags = false;
+        Ok(tags)
+    }
+
+    // After an error, skips the rest of the bad game so that the next one can
+    // be read. Games are taken to start at a line beginning with a tag that
+    // follows a line that is not a tag.
+    fn skip_to_next_game(&mut self, err: &PgnError) {
+        self.peeked = None;
+        // A game missing its result runs into the tags of the next one
+        let at_tag = matches!(&err.kind, PgnErrorKind::UnexpectedToken(t) if t == "[");
+        if at_tag && err.col == 1 && !self.in_tags {
+            self.peeked = Some((Token::TagStart, err.line, err.col));
+            return;
+        }
+        if self.lexer.col > 1 {
+            self.lexer.skip_line();
+        }
+        let mut past_tags = !self.in_tags;
+        while let Some(&c) = self.lexer.chars.peek() {
+            if c != '[' {
+                past_tags = true;
+            } else if past_tags {
+                break;
+            }
+            self.lexer.skip_line();
+        }
+        self.in_tags = false;
+    }
+
+    // Reads moves following `start` up to the end of the variation (or game,
+    // at depth 0), returning the game termination marker if one was found
+    fn parse_line(
+        &mut self,
+        tree: &mut FenTree,
+        start: NodeId,
+        depth: usize,
+    ) -> Result<Option<String>, PgnError> {
+        // The node before the last move read, which variations branch off
+        let mut prev = None;
+        let mut curr = start;
+        // Comment read before the first move of a variation
+        let mut comment_before: Option<String> = None;
+        loop {
+            let Some((token, line, col)) = self.next()? else {
+                if depth > 0 {
+                    return Err(self.unexpected(None));
+                }
+                return Ok(None);
+            };
+            match token {
+                Token::Symbol(s) if s.bytes().all(|b| b.is_ascii_digit()) => {}
+                Token::Period => {}
+                Token::Symbol(s) => {
+                    let mov = tree
+                        .store
+                        .get(curr)
+                        .fen
+                        .parse_move(&s)
+                        .map_err(|e| PgnError {
+                            line,
+                            col,
+                            kind: e.into(),
+                        })?;
+                    prev = Some(curr);
+                    curr = tree.add_child(curr, mov);
+                    tree.store.get_mut(curr).annotation.comment_before = comment_before.take();
+                }
+                Token::Nag(nag) if prev.is_some() => {
+                    tree.store.get_mut(curr).annotation.add_nag(Nag(nag));
+                }
+                // A comment before the first move of the game is on the game
+                // as a whole, and is kept on the root
+                Token::Comment(comment) if prev.is_some() || depth == 0 => {
+                    let annotation = &mut tree.store.get_mut(curr).annotation;
+                    append_comment(&mut annotation.comment_after, comment);
+                }
+                Token::Comment(comment) => append_comment(&mut comment_before, comment),
+                Token::VariationStart => {
+                    let Some(parent) = prev else {
+                        return Err(PgnError {
+                            line,
+                            col,
+                            kind: PgnErrorKind::VariationWithoutMove,
+                        });
+                    };
+                    self.parse_line(tree, parent, depth + 1)?;
+                }
+                Token::VariationEnd if depth > 0 => return Ok(None),
+                Token::Result(result) if depth == 0 => return Ok(Some(result)),
+                token => return Err(self.unexpected(Some((token, line, col)))),
+            }
+        }
+    }
+}
+
+fn append_comment(dest: &mut Option<String>, comment: String) {
+    match dest {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(&comment);
+        }
+        None => *dest = Some(comment),
+    }
+}
+
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+// Export format lines should not exceed 79 characters
+const MAX_LINE_LEN: usize = 79;
+
+impl Game {
+    /// Parses the first game in the PGN text.
+    pub fn from_pgn(pgn: &str) -> Result<Self, PgnError> {
+        PgnParser::new(pgn).next_game()?.ok_or(PgnError {
+            line: 1,
+            col: 1,
+            kind: PgnErrorKind::NoGame,
+        })
+    }
+
+    /// Parses every game in the PGN text. A game with an error is given as
+    /// that error, and reading goes on from the tags of the next game.
+    pub fn all_from_pgn(pgn: &str) -> impl Iterator<Item = Result<Self, PgnError>> + '_ {
+        let mut parser = PgnParser::new(pgn);
+        std::iter::from_fn(move || {
+            let game = parser.next_game().transpose();
+            if let Some(Err(err)) = &game {
+                parser.skip_to_next_game(err);
+            }
+            game
+        })
+    }
+
+    /// Writes the game, with its variations and annotations, in PGN export
+    /// format.
+    pub fn to_pgn(&self) -> String {
+        self.to_pgn_with(Notation::San)
+    }
+
+    /// Like [`Game::to_pgn`], writing the moves in the given notation rather
+    /// than SAN, which the export format prescribes.
+    pub fn to_pgn_with(&self, notation: Notation) -> String {
+        let result = self.pgn_result();
+        let mut tags: Vec<(&str, &str)> = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|&(name, default)| (name, self.tag(name).unwrap_or(default)))
+            .collect();
+        tags.iter_mut()
+            .find(|(name, _)| *name == "Result")
+            .unwrap()
+            .1 = &result;
+        let root_fen = self.tree.store.get(self.tree.root).fen.to_string();
+        if root_fen != INIT_FEN && self.tag("FEN").is_none() {
+            tags.push(("SetUp", "1"));
+            tags.push(("FEN", &root_fen));
+        }
+        tags.extend(
+            self.tags
+                .iter()
+                .filter(|(name, _)| SEVEN_TAG_ROSTER.iter().all(|(n, _)| n != name))
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(pgn, "[{} \"{}\"]", name, value).unwrap();
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let root = self.tree.store.get(self.tree.root);
+        if let Some(comment) = &root.annotation.comment_after {
+            write_comment(comment, &mut tokens);
+        }
+        write_line(&self.tree, self.tree.root, true, notation, &mut tokens);
+        tokens.push(result);
+
+        let mut line_len = 0;
+        let mut prev: Option<&str> = None;
+        for token in &tokens {
+            let needs_space = prev.is_some_and(|p| p != "(") && token != ")";
+            if needs_space && line_len + 1 + token.len() > MAX_LINE_LEN {
+                pgn.push('\n');
+                line_len = 0;
+            } else if needs_space {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            pgn.push_str(token);
+            line_len += token.len();
+            prev = Some(token);
+        }
+        pgn.push('\n');
+        pgn
+    }
+
+    // The result of the line followed forward from the start, falling back to
+    // the imported result tag when that line has not been played to the end
+    fn pgn_result(&self) -> String {
+        let tree = &self.tree;
+        let mut idx = tree.root;
+        while let Some((_, next)) = tree.store.get(idx).next_child {
+            idx = next;
+        }
+        match tree.store.get(idx).status {
+            GameStatus::Over(result, _) => result.to_string(),
+            GameStatus::Ongoing => self.tag("Result").unwrap_or("*").to_string(),
+        }
+    }
+}
+
+fn write_line(
+    tree: &FenTree,
+    mut idx: NodeId,
+    mut force_number: bool,
+    notation: Notation,
+    tokens: &mut Vec<String>,
+) {
+    while let Some((mov, next)) = tree.store.get(idx).next_child {
+        let node = tree.store.get(idx);
+        force_number = write_move(tree, &node.fen, mov, next, force_number, notation, tokens);
+        for &(alt, alt_idx) in node.children.iter().filter(|&&(_, i)| i != next) {
+            tokens.push("(".to_string());
+            let alt_force = write_move(tree, &node.fen, alt, alt_idx, true, notation, tokens);
+            write_line(tree, alt_idx, alt_force, notation, tokens);
+            tokens.push(")".to_string());
+            // The main line resumes with its move number after a variation
+            force_number = true;
+        }
+        idx = next;
+    }
+}
+
+// Writes the move leading to `idx` along with its annotation, returning
+// whether the next move needs its number repeated
+fn write_move(
+    tree: &FenTree,
+    fen: &Fen,
+    mov: Move,
+    idx: NodeId,
+    mut force_number: bool,
+    notation: Notation,
+    tokens: &mut Vec<String>,
+) -> bool {
+    let annotation = &tree.store.get(idx).annotation;
+    if let Some(comment) = &annotation.comment_before {
+        write_comment(comment, tokens);
+        force_number = true;
+    }
+    match fen.to_move {
+        Colour::White => tokens.push(format!("{}.", fen.move_cnt)),
+        Colour::Black if force_number => tokens.push(format!("{}...", fen.move_cnt)),
+        Colour::Black => {}
+    }
+    tokens.push(fen.notation(mov, notation));
+    tokens.extend(annotation.nags.iter().map(|nag| format!("${}", nag.0)));
+    if let Some(comment) = &annotation.comment_after {
+        write_comment(comment, tokens);
+        return true;
+    }
+    false
+}
+
+// Comments are split into words so that long ones can be wrapped, which turns
+// runs of whitespace into single spaces. PGN has no way to escape a `}` in a
+// comment, so it is written as `)`; such a comment reads back changed.
+fn write_comment(comment: &str, tokens: &mut Vec<String>) {
+    let comment = comment.replace('}', ")");
+    let mut words: Vec<String> = comment.split_whitespace().map(str::to_string).collect();
+    if words.is_empty() {
+        words.push(String::new());
+    }
+    words[0].insert(0, '{');
+    words.last_mut().unwrap().push('}');
+    tokens.extend(words);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Casual game"]
+[White "Anderssen"]
+[Black "Kieseritzky"]
+[Result "*"]
+
+% This line is ignored
+1. e4 e5 2. f4 {King's Gambit} exf4 $1 (2... d5!? 3. exd5 (3. Nf3) c6) 3. Bc4 Qh4+
+4. Kf1 ; the king has to move
+b5?! 1-0
+"#;
+
+    #[test]
+    fn parse_game() {
+        let game = Game::from_pgn(PGN).unwrap();
+        assert_eq!(game.tag("White"), Some("Anderssen"));
+        assert_eq!(game.tag("Result"), Some("1-0"));
+        let notation: Vec<_> = game
+            .game_moves()
+            .into_iter()
+            .map(|gm| gm.notation)
+            .collect();
+        assert_eq!(
+            notation,
+            ["e4", "e5", "f4", "exf4", "Bc4", "Qh4+", "Kf1", "b5"]
+        );
+
+        // 2... d5 is an alternative to 2... exf4, with a nested 3. Nf3 in it
+        let tree = &game.tree;
+        let after_f4 = (0..3).fold(tree.root, |idx, _| {
+            tree.store.get(idx).next_child.unwrap().1
+        });
+        let children = &tree.store.get(after_f4).children;
+        assert_eq!(children.len(), 2);
+        let (_, d5) = children[1];
+        let (_, exd5) = tree.store.get(d5).next_child.unwrap();
+        assert_eq!(tree.store.get(d5).children.len(), 2);
+        assert_eq!(tree.store.get(exd5).children.len(), 1);
+    }
+
+    #[test]
+    fn parse_from_fen_tag() {
+        let pgn = r#"[FEN "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1"] 1. O-O-O+ Kf7 *"#;
+        let game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(game.game_moves().len(), 2);
+        assert_eq!(game.tag("Result"), Some("*"));
+    }
+
+    #[test]
+    fn export_game() {
+        let game = Game::from_pgn(PGN).unwrap();
+        let expected = r#"[Event "Casual game"]
+[Site "?"]
+[Date "????.??.??"]
+[Round "?"]
+[White "Anderssen"]
+[Black "Kieseritzky"]
+[Result "1-0"]
+
+1. e4 e5 2. f4 {King's Gambit} 2... exf4 $1 (2... d5 $5 3. exd5 (3. Nf3) 3...
+c6) 3. Bc4 Qh4+ 4. Kf1 b5 $6 1-0
+"#;
+        assert_eq!(game.to_pgn(), expected);
+        assert_eq!(Game::from_pgn(&game.to_pgn()).unwrap().to_pgn(), expected);
+    }
+
+    #[test]
+    fn annotations() {
+        let pgn = "{Opening} 1. e4 $14 {Best by test} (1. d4 $10) ({Also} 1. c4!?) 1... e5 *";
+        let mut game = Game::from_pgn(pgn).unwrap();
+        let exported = game.to_pgn();
+        assert!(exported.ends_with(
+            "{Opening} 1. e4 $14 {Best by test} (1. d4 $10) ({Also} 1. c4 $5) 1... e5 *\n"
+        ));
+        assert_eq!(Game::from_pgn(&exported).unwrap().to_pgn(), exported);
+
+        assert_eq!(game.annotation().comment_after.as_deref(), Some("Opening"));
+        game.forward();
+        let annotation = game.annotation();
+        assert_eq!(annotation.nags, vec![Nag(14)]);
+        assert_eq!(annotation.nags[0].to_string(), "+=");
+        assert_eq!(annotation.comment_after.as_deref(), Some("Best by test"));
+        game.next_variation();
+        game.next_variation();
+        assert_eq!(game.annotation().comment_before.as_deref(), Some("Also"));
+        assert_eq!(game.annotation().nags, vec![Nag(5)]);
+
+        game.annotation_mut().toggle_nag(Nag(1));
+        assert_eq!(game.annotation().nags, vec![Nag(1)]);
+        game.annotation_mut().toggle_nag(Nag(1));
+        assert!(game.annotation().nags.is_empty());
+
+        // NAGs are read into order, once each, however they are written
+        let expected = "1. e4 $1 $2 $14 (1. d4 $1 $14) *\n";
+        for pgn in [
+            "1. e4 $14 $2 $1 (1. d4 $14 $1) *",
+            "1. e4! $2 $1 $14 $1 (1. d4 $14 $1 $14) *",
+            expected,
+        ] {
+            let mut game = Game::from_pgn(pgn).unwrap();
+            assert!(game.to_pgn().ends_with(expected), "{}", pgn);
+            game.forward();
+            assert_eq!(game.annotation().nags, [Nag(1), Nag(2), Nag(14)]);
+            game.annotation_mut().toggle_nag(Nag(2));
+            assert_eq!(game.annotation().nags, [Nag(1), Nag(14)]);
+        }
+    }
+
+    #[test]
+    fn comment_braces() {
+        let mut game = Game::from_pgn("1. e4 *").unwrap();
+        game.forward();
+        game.annotation_mut().comment_after = Some("a {b} c".to_string());
+        let exported = game.to_pgn();
+        assert!(exported.ends_with("1. e4 {a {b) c} *\n"));
+        let mut game = Game::from_pgn(&exported).unwrap();
+        game.forward();
+        assert_eq!(game.annotation().comment_after.as_deref(), Some("a {b) c"));
+        assert_eq!(game.to_pgn(), exported);
+    }
+
+    #[test]
+    fn export_from_position() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b Q - 0 40";
+        let mut game = Game::from_fen(fen).unwrap();
+        game.apply_move(game.parse_san("Kf7").unwrap());
+        game.apply_move(game.parse_san("O-O-O").unwrap());
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 40\"]\n"));
+        assert!(pgn.ends_with("\n40... Kf7 41. O-O-O *\n"));
+    }
+
+    #[test]
+    fn convert_notation() {
+        let game = Game::from_pgn(PGN).unwrap();
+        let uci = game.to_pgn_with(Notation::Uci);
+        assert!(uci.contains("1. e2e4 e7e5 2. f2f4 {King's Gambit} 2... e5f4 $1 (2... d7d5 $5"));
+        let lan = game.to_pgn_with(Notation::Lan);
+        assert!(lan.contains("3. Bf1-c4 Qd8-h4+ 4. Ke1-f1"));
+        // Either converts back to the same game
+        for pgn in [uci, lan] {
+            assert_eq!(Game::from_pgn(&pgn).unwrap().to_pgn(), game.to_pgn());
+        }
+    }
+
+    #[test]
+    fn all_games() {
+        let pgn = format!("{}\n1. d4 d5 *\n\n1. e4 e4 *\n\n1. c4 *\n", PGN);
+        let games: Vec<_> = Game::all_from_pgn(&pgn).collect();
+        assert_eq!(games.len(), 3);
+        assert!(games[0].is_ok() && games[1].is_ok());
+        let err = games[2].as_ref().unwrap_err();
+        assert_eq!((err.line, err.col), (13, 7));
+        assert!(matches!(err.kind, PgnErrorKind::Move(_)));
+
+        // Games after a bad one are still read, whether it went wrong in its
+        // tags, its moves, or by running into the next game's tags
+        let pgn = "[Event \"a\"]\n[Round 1]\n[Site \"x\"]\n\n1. e4 *\n\n\
+                   [Event \"b\"]\n\n1. e4 e4 2. d4\n3. c4 *\n\n\
+                   [Event \"c\"]\n\n1. d4\n\
+                   [Event \"d\"]\n\n1. c4 *\n";
+        let games: Vec<_> = Game::all_from_pgn(pgn).collect();
+        let events: Vec<_> = games
+            .iter()
+            .map(|game| match game {
+                Ok(game) => game.tag("Event").unwrap().to_string(),
+                Err(err) => format!("{}:{}", err.line, err.col),
+            })
+            .collect();
+        assert_eq!(events, ["2:8", "9:7", "15:1", "d"]);
+    }
+
+    #[test]
+    fn export_wraps_lines() {
+        let mut game = Game::new();
+        for _ in 0..20 {
+            for san in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+                if !game.status().is_over() {
+                    game.apply_move(game.parse_san(san).unwrap());
+                }
+            }
+        }
+        let pgn = game.to_pgn();
+        assert!(pgn.lines().all(|line| line.len() <= MAX_LINE_LEN));
+        assert!(pgn.ends_with(" 1/2-1/2\n"));
+    }
+
+    #[test]
+    fn error_positions() {
+        let err = Game::from_pgn("[Event \"x\"]\n1. e4 e5\n2. Ke3 *").unwrap_err();
+        assert_eq!((err.line, err.col), (3, 4));
+        assert!(matches!(
+            err.kind,
+            PgnErrorKind::Move(MoveParseError::Illegal(_))
+        ));
+
+        let err = Game::from_pgn("1. e4 (1. d4 d5").unwrap_err();
+        assert!(matches!(err.kind, PgnErrorKind::UnexpectedEof));
+
+        let err = Game::from_pgn("1. e4 {unterminated").unwrap_err();
+        assert_eq!((err.line, err.col), (1, 7));
+
+        let err = Game::from_pgn("1. e4 e5 )").unwrap_err();
+        assert_eq!((err.line, err.col), (1, 10));
+
+        let err = Game::from_pgn("(1. e4)").unwrap_err();
+        assert!(matches!(err.kind, PgnErrorKind::VariationWithoutMove));
+    }
+}
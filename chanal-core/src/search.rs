@@ -0,0 +1,876 @@
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Colour, EvalSource, Fen, Game, Move, Piece, Position, Score};
+
+const INF: i32 = 32000;
+const MATE: i32 = 31000;
+// Scores beyond this are mates, with the plies to mate taken off MATE
+const MATE_BOUND: i32 = MATE - 1000;
+const MAX_DEPTH: u32 = 64;
+const MAX_PLY: u32 = 128;
+const TT_SIZE: usize = 1 << 16;
+// The clock and stop flag are looked at once every this many nodes
+const CHECK_INTERVAL: u64 = 2048;
+
+/// How far a search may go. Limits left unset don't apply, so with none set
+/// a search only ends when asked to stop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Deepest iteration, in plies.
+    pub depth: Option<u32>,
+    /// Time to think; iterations that would overrun it are cut short.
+    pub time: Option<Duration>,
+    /// Nodes to search, counting quiescence nodes.
+    pub nodes: Option<u64>,
+}
+
+/// What a search found at the deepest iteration it completed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// Depth of the deepest completed iteration, in plies. A search stopped
+    /// during the first has depth 0, though it still has a move.
+    pub depth: u32,
+    /// Score for the side to move.
+    pub score: Score,
+    /// The expected line, starting with the best move. Empty when there is
+    /// no legal move.
+    pub pv: Vec<Move>,
+    /// Nodes visited over all iterations, counting quiescence nodes.
+    pub nodes: u64,
+}
+
+impl SearchResult {
+    /// The first move of the principal variation, if there is one.
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().copied()
+    }
+}
+
+/// Searches the position within the limits, on the calling thread.
+pub fn search(fen: &Fen, limits: Limits) -> SearchResult {
+    let mut tt = TranspositionTable::new();
+    let stop = AtomicBool::new(false);
+    Searcher::new(&mut tt, limits, &stop, Vec::new()).run(fen, |_| {})
+}
+
+// Material in centipawns
+const fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+// Piece-square tables for white, indexed like Board so the first row is the
+// eighth rank; black's are the same turned upside down
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+// Once the heavy pieces are gone the king should come to the centre
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// Pieces other than kings and pawns worth no more than this, summed over both
+// sides, make an endgame
+const ENDGAME_MATERIAL: i32 = 2 * (piece_value(Piece::Rook) + piece_value(Piece::Bishop));
+
+// Static evaluation for the side to move
+fn evaluate(fen: &Fen) -> i32 {
+    let mut score = 0;
+    let mut material = 0;
+    let mut kings = [0; 2];
+    for (rank, row) in fen.board.iter().enumerate() {
+        for (file, pos) in row.iter().enumerate() {
+            let Position::Occupied(piece, colour) = *pos else {
+                continue;
+            };
+            let idx = match colour {
+                Colour::White => rank * 8 + file,
+                Colour::Black => (7 - rank) * 8 + file,
+            };
+            let table = match piece {
+                Piece::Pawn => &PAWN_TABLE,
+                Piece::Knight => &KNIGHT_TABLE,
+                Piece::Bishop => &BISHOP_TABLE,
+                Piece::Rook => &ROOK_TABLE,
+                Piece::Queen => &QUEEN_TABLE,
+                Piece::King => {
+                    kings[colour as usize] = idx;
+                    continue;
+                }
+            };
+            if piece != Piece::Pawn {
+                material += piece_value(piece);
+            }
+            let value = piece_value(piece) + table[idx];
+            score += if colour == Colour::White {
+                value
+            } else {
+                -value
+            };
+        }
+    }
+    let king_table = if material <= ENDGAME_MATERIAL {
+        &KING_ENDGAME_TABLE
+    } else {
+        &KING_TABLE
+    };
+    score += king_table[kings[Colour::White as usize]] - king_table[kings[Colour::Black as usize]];
+    if fen.to_move == Colour::White {
+        score
+    } else {
+        -score
+    }
+}
+
+// MVV-LVA: the most valuable victim first, and of those the least valuable
+// attacker; the transposition table's move goes before everything
+fn order_moves(moves: &mut [Move], tt_move: Option<Move>) {
+    moves.sort_by_key(|&mov| {
+        if Some(mov) == tt_move {
+            return Reverse(i32::MAX);
+        }
+        let mut key = 0;
+        if let Some(cap) = mov.capture {
+            key += 10_000 + 10 * piece_value(cap.piece) - piece_value(mov.piece);
+        }
+        if let Some(piece) = mov.promotion() {
+            key += piece_value(piece);
+        }
+        Reverse(key)
+    });
+}
+
+fn to_score(score: i32) -> Score {
+    if score.abs() > MATE_BOUND {
+        let moves = (MATE - score.abs() + 1) / 2;
+        Score::Mate(if score > 0 { moves } else { -moves })
+    } else {
+        Score::Cp(score)
+    }
+}
+
+// Mate scores are stored relative to the node rather than the root, so that
+// they stay right when the position is reached at another ply
+fn score_to_tt(score: i32, ply: u32) -> i32 {
+    match score {
+        s if s > MATE_BOUND => s + ply as i32,
+        s if s < -MATE_BOUND => s - ply as i32,
+        s => s,
+    }
+}
+
+fn score_from_tt(score: i32, ply: u32) -> i32 {
+    match score {
+        s if s > MATE_BOUND => s - ply as i32,
+        s if s < -MATE_BOUND => s + ply as i32,
+        s => s,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    // The score is at least this, the search having been cut off
+    Lower,
+    // No move reached alpha, so the score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TtEntry {
+    hash: u64,
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    mov: Option<Move>,
+}
+
+struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+}
+
+impl TranspositionTable {
+    fn new() -> Self {
+        Self {
+            entries: vec![None; TT_SIZE],
+        }
+    }
+
+    fn get(&self, hash: u64) -> Option<&TtEntry> {
+        let entry = self.entries[hash as usize % TT_SIZE].as_ref()?;
+        (entry.hash == hash).then_some(entry)
+    }
+
+    // A deeper result for the same position is not replaced by a shallower one
+    fn store(&mut self, entry: TtEntry) {
+        let slot = &mut self.entries[entry.hash as usize % TT_SIZE];
+        if let Some(old) = slot {
+            if old.hash == entry.hash && old.depth > entry.depth {
+                return;
+            }
+        }
+        *slot = Some(entry);
+    }
+}
+
+struct Searcher<'a> {
+    tt: &'a mut TranspositionTable,
+    limits: Limits,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    aborted: bool,
+    // Hashes of the positions before the current one, for repetitions
+    history: Vec<u64>,
+    root_best: Option<Move>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(
+        tt: &'a mut TranspositionTable,
+        limits: Limits,
+        stop: &'a AtomicBool,
+        history: Vec<u64>,
+    ) -> Self {
+        Self {
+            tt,
+            limits,
+            stop,
+            start: Instant::now(),
+            nodes: 0,
+            aborted: false,
+            history,
+            root_best: None,
+        }
+    }
+
+    // Iterative deepening, reporting each iteration as it completes
+    fn run(&mut self, fen: &Fen, mut report: impl FnMut(&SearchResult)) -> SearchResult {
+        let mut fen = *fen;
+        let in_check = fen.board.king_check_cnt().check_cnt(fen.to_move) != 0;
+        let max_depth = self.limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        let mut result = SearchResult {
+            depth: 0,
+            score: Score::Cp(evaluate(&fen)),
+            pv: Vec::new(),
+            nodes: 0,
+        };
+        for depth in 1..=max_depth {
+            self.root_best = None;
+            let score = self.alpha_beta(&mut fen, depth, 0, -INF, INF, in_check);
+            if self.aborted {
+                if depth == 1 {
+                    result.pv = self.first_move(&fen).into_iter().collect();
+                    result.nodes = self.nodes;
+                }
+                break;
+            }
+            result = SearchResult {
+                depth,
+                score: to_score(score),
+                pv: self.pv(&fen, depth),
+                nodes: self.nodes,
+            };
+            report(&result);
+
+            // Iterative deepening finds the shortest mate first
+            if score.abs() > MATE_BOUND || result.pv.is_empty() {
+                break;
+            }
+            // The next iteration would most likely not finish in time
+            if let Some(time) = self.limits.time {
+                if self.start.elapsed() > time / 2 {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    // The move to play when stopped before the first iteration is over: the
+    // best of the root moves searched fully, or else the first to be searched
+    fn first_move(&self, fen: &Fen) -> Option<Move> {
+        self.root_best.or_else(|| {
+            let mut moves = fen.generate_moves();
+            order_moves(&mut moves, None);
+            moves.first().copied()
+        })
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        if self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes) {
+            self.aborted = true;
+        } else if self.nodes % CHECK_INTERVAL == 0 {
+            let out_of_time = self
+                .limits
+                .time
+                .is_some_and(|time| self.start.elapsed() >= time);
+            self.aborted = out_of_time || self.stop.load(Ordering::Relaxed);
+        }
+        self.aborted
+    }
+
+    fn is_draw(&self, fen: &Fen) -> bool {
+        // Only positions since the last capture or pawn move can repeat
+        let repeated = self
+            .history
+            .iter()
+            .rev()
+            .take(fen.halfmove_clock as usize)
+            .any(|&hash| hash == fen.hash);
+        repeated || fen.halfmove_clock >= 100 || fen.board.has_insufficient_material()
+    }
+
+    fn alpha_beta(
+        &mut self,
+        fen: &mut Fen,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        beta: i32,
+        in_check: bool,
+    ) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && self.is_draw(fen) {
+            return 0;
+        }
+        if ply >= MAX_PLY {
+            return evaluate(fen);
+        }
+        // Checks are searched a ply deeper rather than left to quiescence
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 {
+            return self.quiescence(fen, alpha, beta);
+        }
+        self.nodes += 1;
+
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.get(fen.hash) {
+            tt_move = entry.mov;
+            let score = score_from_tt(entry.score, ply);
+            if ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let mut moves = fen.generate_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        order_moves(&mut moves, tt_move);
+
+        let alpha_orig = alpha;
+        let mut best = -INF;
+        let mut best_move = None;
+        for mov in moves {
+            self.history.push(fen.hash);
+            let undo = fen.make_move(mov);
+            let score = -self.alpha_beta(fen, depth - 1, ply + 1, -beta, -alpha, mov.has_check());
+            fen.unmake_move(undo);
+            self.history.pop();
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(mov);
+                if ply == 0 {
+                    self.root_best = best_move;
+                }
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= alpha_orig {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.tt.store(TtEntry {
+            hash: fen.hash,
+            depth,
+            score: score_to_tt(best, ply),
+            bound,
+            mov: best_move,
+        });
+        best
+    }
+
+    // Plays out captures and promotions until the position is quiet, so that
+    // the evaluation isn't taken in the middle of an exchange
+    fn quiescence(&mut self, fen: &mut Fen, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let stand_pat = evaluate(fen);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = fen.generate_moves();
+        moves.retain(|mov| mov.has_capture() || mov.promotion().is_some());
+        order_moves(&mut moves, None);
+
+        let mut best = stand_pat;
+        for mov in moves {
+            let undo = fen.make_move(mov);
+            let score = -self.quiescence(fen, -beta, -alpha);
+            fen.unmake_move(undo);
+            if self.aborted {
+                return 0;
+            }
+
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    // The root's best move followed by those the table holds
+    fn pv(&self, fen: &Fen, depth: u32) -> Vec<Move> {
+        let mut fen = *fen;
+        let mut pv = Vec::new();
+        let mut next = self.root_best;
+        while let Some(mov) = next {
+            // Different positions can share a slot, so the move is checked
+            if pv.len() >= depth as usize || !fen.generate_moves().contains(&mov) {
+                break;
+            }
+            pv.push(mov);
+            fen.make_move(mov);
+            next = self.tt.get(fen.hash).and_then(|entry| entry.mov);
+        }
+        pv
+    }
+}
+
+struct Job {
+    id: u64,
+    fen: Fen,
+    history: Vec<u64>,
+    limits: Limits,
+    stop: Arc<AtomicBool>,
+}
+
+struct Report {
+    id: u64,
+    result: SearchResult,
+    done: bool,
+}
+
+/// The built-in engine, searching on a thread of its own so that its caller
+/// never waits on it. The transposition table is kept from one search to the
+/// next.
+pub struct Computer {
+    jobs: Sender<Job>,
+    reports: Receiver<Report>,
+    // Each search has its own flag, so stopping one never stops the next
+    stop: Arc<AtomicBool>,
+    job: u64,
+    thinking: bool,
+    fen: Option<Fen>,
+    latest: Option<SearchResult>,
+}
+
+impl Default for Computer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Computer {
+    /// Starts the search thread, idle until [`Computer::think`] is called.
+    pub fn new() -> Self {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (report_tx, reports) = mpsc::channel();
+        thread::spawn(move || {
+            let mut tt = TranspositionTable::new();
+            for job in job_rx {
+                let mut searcher = Searcher::new(&mut tt, job.limits, &job.stop, job.history);
+                let result = searcher.run(&job.fen, |result| {
+                    let _ = report_tx.send(Report {
+                        id: job.id,
+                        result: result.clone(),
+                        done: false,
+                    });
+                });
+                let _ = report_tx.send(Report {
+                    id: job.id,
+                    result,
+                    done: true,
+                });
+            }
+        });
+
+        Self {
+            jobs,
+            reports,
+            stop: Arc::new(AtomicBool::new(false)),
+            job: 0,
+            thinking: false,
+            fen: None,
+            latest: None,
+        }
+    }
+
+    /// Starts searching the current position of the game, abandoning any
+    /// search still going.
+    pub fn think(&mut self, game: &Game, limits: Limits) {
+        self.stop();
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.job += 1;
+        self.thinking = true;
+        let fen = *game.tree.curr_fen();
+        self.fen = Some(fen);
+        self.latest = None;
+        let _ = self.jobs.send(Job {
+            id: self.job,
+            fen,
+            history: game.position_history(),
+            limits,
+            stop: self.stop.clone(),
+        });
+    }
+
+    /// Ends the search early. Its best move so far still comes from
+    /// [`Computer::poll`].
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a search is running, or has ended but not yet been [`Computer::poll`]ed.
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    /// The deepest iteration of the current search completed so far.
+    pub fn latest(&self) -> Option<&SearchResult> {
+        self.latest.as_ref()
+    }
+
+    /// Takes in what the search thread has found since the last call. Once the
+    /// search is over this returns its best move, if there is a legal one.
+    pub fn poll(&mut self) -> Option<Move> {
+        let mut best = None;
+        loop {
+            let report = match self.reports.try_recv() {
+                Ok(report) => report,
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => return best,
+            };
+            // Reports of abandoned searches are dropped
+            if report.id != self.job {
+                continue;
+            }
+            if report.done {
+                self.thinking = false;
+                best = report.result.best_move();
+            }
+            if report.result.depth > 0 {
+                self.latest = Some(report.result);
+            }
+        }
+    }
+}
+
+impl EvalSource for Computer {
+    fn score(&self) -> Option<Score> {
+        let fen = self.fen.as_ref()?;
+        Some(self.latest.as_ref()?.score.for_white(fen.to_move))
+    }
+}
+
+impl Drop for Computer {
+    fn drop(&mut self) {
+        // Dropping the job sender ends the thread once this search stops
+        self.stop();
+    }
+}
+
+impl Game {
+    // Hashes of the positions leading up to the current one
+    fn position_history(&self) -> Vec<u64> {
+        let tree = &self.tree;
+        let mut history = vec![tree.store.get(tree.root).fen.hash];
+        let path = tree.path_to_curr();
+        history.extend(path.iter().map(|&node| tree.store.get(node).fen.hash));
+        history.pop();
+        history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::INIT_FEN;
+
+    fn best_uci(fen: &str, limits: Limits) -> (String, Score) {
+        let fen: Fen = fen.parse().unwrap();
+        let result = search(&fen, limits);
+        (result.best_move().unwrap().uci(), result.score)
+    }
+
+    #[test]
+    fn evaluation() {
+        let start: Fen = INIT_FEN.parse().unwrap();
+        assert_eq!(evaluate(&start), 0);
+        let fen: Fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+            .parse()
+            .unwrap();
+        assert!(evaluate(&fen) < 0);
+        // Mirroring the position and the side to move keeps the evaluation
+        let mirrored: Fen = "rnbqkbnr/pppp1ppp/8/4p3/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1"
+            .parse()
+            .unwrap();
+        assert_eq!(evaluate(&fen), evaluate(&mirrored));
+    }
+
+    #[test]
+    fn tactics() {
+        let depth = |depth| Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        };
+        // Back rank mate
+        let (mov, score) = best_uci("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", depth(3));
+        assert_eq!((mov.as_str(), score), ("d1d8", Score::Mate(1)));
+        // A free queen is taken, while quiescence sees the pawn recapturing a
+        // defended rook
+        let (mov, _) = best_uci("4k3/8/8/3q4/8/8/8/3QK3 w - - 0 1", depth(2));
+        assert_eq!(mov, "d1d5");
+        let (mov, _) = best_uci("4k3/4p3/3r4/8/8/8/8/3QK3 w - - 0 1", depth(1));
+        assert_ne!(mov, "d1d6");
+
+        // Being mated, and stalemate, leave no move
+        let fen: Fen = "3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1".parse().unwrap();
+        let result = search(&fen, depth(3));
+        assert_eq!((result.best_move(), result.score), (None, Score::Mate(0)));
+
+        let fen: Fen = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1".parse().unwrap();
+        let result = search(&fen, depth(3));
+        assert_eq!((result.best_move(), result.score), (None, Score::Cp(0)));
+    }
+
+    #[test]
+    fn limits() {
+        let fen: Fen = INIT_FEN.parse().unwrap();
+        let result = search(
+            &fen,
+            Limits {
+                nodes: Some(5000),
+                ..Limits::default()
+            },
+        );
+        assert!(result.depth >= 1 && result.best_move().is_some());
+        assert!(result.nodes <= 5000);
+
+        let start = Instant::now();
+        let result = search(
+            &fen,
+            Limits {
+                time: Some(Duration::from_millis(100)),
+                ..Limits::default()
+            },
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(result.best_move().is_some());
+    }
+
+    #[test]
+    fn early_stop() {
+        // Checks are extended, so even the first iteration here is long
+        let fen: Fen = "r1b1kb1r/pp3ppp/2n1pn2/q1pp4/3P4/2PBPN2/PP1N1PPP/R2QK2R w KQkq - 0 1"
+            .parse()
+            .unwrap();
+        let legal = fen.generate_moves();
+
+        let result = search(
+            &fen,
+            Limits {
+                nodes: Some(1),
+                ..Limits::default()
+            },
+        );
+        assert_eq!(result.depth, 0);
+        assert!(result.nodes <= 1);
+        assert!(legal.contains(&result.best_move().unwrap()));
+
+        // A stop asked for before the search starts ends it straight away
+        let stop = AtomicBool::new(true);
+        let mut tt = TranspositionTable::new();
+        let start = Instant::now();
+        let result = Searcher::new(&mut tt, Limits::default(), &stop, Vec::new()).run(&fen, |_| {});
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!((result.depth, result.nodes), (0, 0));
+        assert!(legal.contains(&result.best_move().unwrap()));
+    }
+
+    #[test]
+    fn repetition() {
+        let mut game = Game::from_fen("7k/8/8/8/3R4/8/8/K7 w - - 0 1").unwrap();
+        for uci in ["a1a2", "h8g8", "a2a1", "g8h8", "a1a2"] {
+            let mov = game.parse_uci(uci).unwrap();
+            game.apply_move(mov);
+        }
+        let history = game.position_history();
+        assert_eq!(history.len(), 5);
+        let fen = *game.tree.curr_fen();
+        let stop = AtomicBool::new(false);
+        let mut tt = TranspositionTable::new();
+        let searcher = Searcher::new(&mut tt, Limits::default(), &stop, history);
+        // Back on g8 is a repetition of the position after 1... Kg8
+        let mut next = fen;
+        next.make_move(next.parse_uci("h8g8").unwrap());
+        assert!(searcher.is_draw(&next));
+        let mut next = fen;
+        next.make_move(next.parse_uci("h8h7").unwrap());
+        assert!(!searcher.is_draw(&next));
+    }
+
+    #[test]
+    fn background_search() {
+        let mut game = Game::new();
+        let mut computer = Computer::new();
+        let limits = Limits {
+            depth: Some(3),
+            ..Limits::default()
+        };
+        // Abandoned for the position after 1. e4 before it can finish
+        computer.think(
+            &game,
+            Limits {
+                depth: Some(30),
+                ..Limits::default()
+            },
+        );
+        let mov = game.parse_san("e4").unwrap();
+        game.apply_move(mov);
+        computer.think(&game, limits);
+        assert!(computer.is_thinking());
+
+        let start = Instant::now();
+        let mov = loop {
+            if let Some(mov) = computer.poll() {
+                break mov;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "search timed out"
+            );
+            thread::sleep(Duration::from_millis(5));
+        };
+        assert!(!computer.is_thinking());
+        assert_eq!(mov.colour(), Colour::Black);
+        assert_eq!(computer.latest().unwrap().depth, 3);
+        assert!(computer.score().is_some());
+    }
+}
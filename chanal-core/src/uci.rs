@@ -0,0 +1,501 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use super::{Colour, Fen, Game, Notation};
+
+/// How to start a UCI engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineConfig {
+    /// The engine executable.
+    pub path: PathBuf,
+    /// Arguments passed to the executable.
+    pub args: Vec<String>,
+    /// Number of lines the engine is asked to analyse.
+    pub multipv: u32,
+}
+
+impl EngineConfig {
+    /// Runs the executable without arguments, asking for three lines.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            args: Vec::new(),
+            multipv: 3,
+        }
+    }
+}
+
+/// Something going wrong while talking to an engine.
+#[derive(Debug, thiserror::Error)]
+pub enum EngineError {
+    #[error("Failed to start engine {0}: {1}")]
+    Spawn(String, io::Error),
+    #[error("Engine exited")]
+    Exited,
+    #[error("Failed to write to engine: {0}")]
+    Write(#[from] io::Error),
+}
+
+/// An engine's evaluation of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// Advantage in hundredths of a pawn.
+    Cp(i32),
+    /// Mate in this many moves, negative when being mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// UCI scores are for the side to move; this turns one into white's.
+    pub fn for_white(self, to_move: Colour) -> Self {
+        match (self, to_move) {
+            (score, Colour::White) => score,
+            (Score::Cp(cp), Colour::Black) => Score::Cp(-cp),
+            (Score::Mate(n), Colour::Black) => Score::Mate(-n),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Score::Cp(cp) => write!(f, "{:+.2}", cp as f64 / 100.0),
+            Score::Mate(n) if n < 0 => write!(f, "#-{}", -n),
+            Score::Mate(n) => write!(f, "#{}", n),
+        }
+    }
+}
+
+/// Anything that can score the position it was last asked about, such as an
+/// engine analysing it.
+pub trait EvalSource {
+    /// Score from white's point of view, once there is one.
+    fn score(&self) -> Option<Score>;
+}
+
+/// The parts of an engine's `info` line chanal shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    /// Search depth in plies, if the engine gave one.
+    pub depth: Option<u32>,
+    /// Rank of the line, starting from 1.
+    pub multipv: u32,
+    /// Score for the side to move.
+    pub score: Option<Score>,
+    /// Principal variation, as UCI moves.
+    pub pv: Vec<String>,
+}
+
+impl Info {
+    /// Parses an `info` line, returning None for any other line.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("info") {
+            return None;
+        }
+        let mut info = Info {
+            depth: None,
+            multipv: 1,
+            score: None,
+            pv: Vec::new(),
+        };
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => info.depth = tokens.next().and_then(|d| d.parse().ok()),
+                "multipv" => info.multipv = tokens.next().and_then(|n| n.parse().ok())?,
+                "score" => {
+                    let kind = tokens.next();
+                    let value = tokens.next().and_then(|v| v.parse().ok());
+                    info.score = match (kind, value) {
+                        (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                        (Some("mate"), Some(n)) => Some(Score::Mate(n)),
+                        _ => None,
+                    };
+                }
+                "pv" => info.pv = tokens.by_ref().map(str::to_string).collect(),
+                // The rest of the line is free text
+                "string" => break,
+                // Other fields and their values are skipped
+                _ => {}
+            }
+        }
+        Some(info)
+    }
+}
+
+/// A running UCI engine analysing one position at a time.
+///
+/// Output is read on a background thread, so [`Engine::poll`] never blocks and
+/// can be called once a frame.
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    output: Receiver<String>,
+    name: Option<String>,
+    multipv: u32,
+    ready: bool,
+    searching: bool,
+    // Searches stopped without their bestmove arriving yet, whose infos are stale
+    stopping: u32,
+    // A position asked for before the engine was ready
+    pending: Option<(String, Fen)>,
+    fen: Option<Fen>,
+    lines: Vec<Info>,
+}
+
+impl Engine {
+    /// Starts the engine and begins the UCI handshake.
+    pub fn spawn(config: &EngineConfig) -> Result<Self, EngineError> {
+        let mut child = Command::new(&config.path)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| EngineError::Spawn(config.path.display().to_string(), err))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (tx, output) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self {
+            child,
+            stdin,
+            output,
+            name: None,
+            multipv: config.multipv.max(1),
+            ready: false,
+            searching: false,
+            stopping: 0,
+            pending: None,
+            fen: None,
+            lines: Vec::new(),
+        };
+        engine.send("uci")?;
+        Ok(engine)
+    }
+
+    /// Name the engine gave in the handshake.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Starts analysing the current position of the game, abandoning the
+    /// previous one.
+    pub fn analyse(&mut self, game: &Game) -> Result<(), EngineError> {
+        let command = game.uci_position();
+        let fen = *game.tree.curr_fen();
+        self.lines.clear();
+        self.fen = None;
+        if self.ready {
+            self.go(command, fen)
+        } else {
+            self.pending = Some((command, fen));
+            Ok(())
+        }
+    }
+
+    /// Position the current lines are for.
+    pub fn position(&self) -> Option<&Fen> {
+        self.fen.as_ref()
+    }
+
+    /// Latest info for each line of the current search, best first.
+    pub fn lines(&self) -> &[Info] {
+        &self.lines
+    }
+
+    /// Handles everything the engine has written since the last call,
+    /// returning whether the lines changed.
+    pub fn poll(&mut self) -> Result<bool, EngineError> {
+        let mut changed = false;
+        loop {
+            let line = match self.output.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(changed),
+                Err(TryRecvError::Disconnected) => return Err(EngineError::Exited),
+            };
+            changed |= self.handle(&line)?;
+        }
+    }
+
+    fn handle(&mut self, line: &str) -> Result<bool, EngineError> {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("id") if tokens.next() == Some("name") => {
+                self.name = Some(tokens.collect::<Vec<_>>().join(" "));
+            }
+            Some("uciok") => {
+                if self.multipv > 1 {
+                    self.send(&format!("setoption name MultiPV value {}", self.multipv))?;
+                }
+                self.send("isready")?;
+            }
+            Some("readyok") if !self.ready => {
+                self.ready = true;
+                if let Some((command, fen)) = self.pending.take() {
+                    self.go(command, fen)?;
+                }
+            }
+            Some("bestmove") if self.stopping > 0 => self.stopping -= 1,
+            Some("bestmove") => self.searching = false,
+            Some("info") if self.stopping == 0 && self.fen.is_some() => {
+                let Some(info) = Info::parse(line) else {
+                    return Ok(false);
+                };
+                if info.score.is_none() || info.pv.is_empty() || info.multipv > self.multipv {
+                    return Ok(false);
+                }
+                let idx = self.lines.partition_point(|l| l.multipv < info.multipv);
+                match self.lines.get_mut(idx) {
+                    Some(l) if l.multipv == info.multipv => *l = info,
+                    _ => self.lines.insert(idx, info),
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn go(&mut self, command: String, fen: Fen) -> Result<(), EngineError> {
+        if self.searching {
+            self.send("stop")?;
+            self.stopping += 1;
+        }
+        self.send(&command)?;
+        self.send("go infinite")?;
+        self.searching = true;
+        self.fen = Some(fen);
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+}
+
+impl EvalSource for Engine {
+    fn score(&self) -> Option<Score> {
+        let fen = self.fen.as_ref()?;
+        Some(self.lines.first()?.score?.for_white(fen.to_move))
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give the engine a moment to quit on its own
+        for _ in 0..20 {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Game {
+    /// The UCI `position` command for the current position. It gives the moves
+    /// from the start of the game so that engines can see repetitions.
+    pub fn uci_position(&self) -> String {
+        let tree = &self.tree;
+        let mut command = format!("position fen {}", tree.store.get(tree.root).fen);
+        let path = tree.path_to_curr();
+        if !path.is_empty() {
+            command += " moves";
+        }
+        for node in path {
+            let parent = tree.store.get(node).parent.unwrap();
+            let (mov, _) = tree
+                .store
+                .get(parent)
+                .children
+                .iter()
+                .find(|(_, n)| *n == node)
+                .unwrap();
+            command += " ";
+            command += &mov.uci();
+        }
+        command
+    }
+}
+
+impl Fen {
+    /// Writes out a line of UCI moves played from this position with move
+    /// numbers, stopping at the first move that is not legal.
+    pub fn line_string(&self, moves: &[String], notation: Notation) -> String {
+        let mut fen = *self;
+        let mut tokens = Vec::new();
+        for (i, uci) in moves.iter().enumerate() {
+            let Ok(mov) = fen.parse_uci(uci) else { break };
+            match fen.to_move {
+                Colour::White => tokens.push(format!("{}.", fen.move_cnt)),
+                Colour::Black if i == 0 => tokens.push(format!("{}...", fen.move_cnt)),
+                Colour::Black => {}
+            }
+            tokens.push(fen.notation(mov, notation));
+            fen.make_move(mov);
+        }
+        tokens.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    // Answers the handshake, and to go with lines depending on the position
+    // last sent; a stop is answered with bestmove as a real engine would
+    const SCRIPTED_ENGINE: &str = r#"
+        while read -r cmd rest; do
+            case "$cmd" in
+                uci) echo "id name Scripted Engine"; echo "uciok" ;;
+                isready) echo "readyok" ;;
+                position) pos="$rest" ;;
+                go)
+                    case "$pos" in
+                        *"moves e2e4 e7e5")
+                            echo "info depth 12 seldepth 18 multipv 1 score cp 31 nodes 9000 pv g1f3 b8c6 f1b5"
+                            echo "info depth 12 seldepth 17 multipv 2 score cp 24 nodes 9000 pv b1c3 g8f6" ;;
+                        *"moves e2e4")
+                            echo "info depth 10 multipv 1 score mate -3 pv e7e5 d1h5" ;;
+                    esac ;;
+                stop) echo "bestmove 0000" ;;
+                quit) exit 0 ;;
+            esac
+        done
+    "#;
+
+    fn scripted_engine() -> Engine {
+        let config = EngineConfig {
+            path: "sh".into(),
+            args: vec!["-c".to_string(), SCRIPTED_ENGINE.to_string()],
+            multipv: 2,
+        };
+        Engine::spawn(&config).unwrap()
+    }
+
+    fn wait_for(engine: &mut Engine, done: impl Fn(&Engine) -> bool) {
+        let start = Instant::now();
+        while !done(engine) {
+            assert!(start.elapsed() < Duration::from_secs(5), "engine timed out");
+            engine.poll().unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn parse_info() {
+        let info = Info::parse(
+            "info depth 20 seldepth 31 multipv 2 score cp -15 upperbound nodes 1234 nps 100 pv d7d5 c2c4",
+        )
+        .unwrap();
+        assert_eq!(info.depth, Some(20));
+        assert_eq!(info.multipv, 2);
+        assert_eq!(info.score, Some(Score::Cp(-15)));
+        assert_eq!(info.pv, vec!["d7d5", "c2c4"]);
+
+        let info = Info::parse("info depth 5 score mate -2 string depth 9 pv a2a3").unwrap();
+        assert_eq!(info.depth, Some(5));
+        assert_eq!(info.multipv, 1);
+        assert_eq!(info.score, Some(Score::Mate(-2)));
+        assert!(info.pv.is_empty());
+
+        assert_eq!(Info::parse("bestmove e2e4 ponder e7e5"), None);
+        assert_eq!(Score::Cp(31).to_string(), "+0.31");
+        assert_eq!(
+            Score::Cp(-150).for_white(Colour::White).to_string(),
+            "-1.50"
+        );
+        assert_eq!(Score::Mate(-3).for_white(Colour::Black).to_string(), "#3");
+        assert_eq!(Score::Mate(2).for_white(Colour::Black).to_string(), "#-2");
+    }
+
+    #[test]
+    fn position_and_lines() {
+        let mut game = Game::new();
+        assert_eq!(
+            game.uci_position(),
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        for san in ["e4", "e5"] {
+            let mov = game.parse_san(san).unwrap();
+            game.apply_move(mov);
+        }
+        assert!(game
+            .uci_position()
+            .ends_with("w KQkq - 0 1 moves e2e4 e7e5"));
+
+        let fen = game.tree.curr_fen();
+        let pv = ["g1f3", "b8c6", "f1b5", "a7a6"].map(String::from);
+        assert_eq!(fen.line_string(&pv, Notation::San), "2. Nf3 Nc6 3. Bb5 a6");
+        let pv = ["d2d4", "e1e8", "g1f3"].map(String::from);
+        assert_eq!(fen.line_string(&pv, Notation::Uci), "2. d2d4");
+        game.back();
+        let fen = game.tree.curr_fen();
+        let pv = ["b8c6", "g1f3"].map(String::from);
+        assert_eq!(fen.line_string(&pv, Notation::San), "1... Nc6 2. Nf3");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scripted_engine_analysis() {
+        let mut engine = scripted_engine();
+        let mut game = Game::new();
+        for san in ["e4", "e5"] {
+            let mov = game.parse_san(san).unwrap();
+            game.apply_move(mov);
+        }
+
+        // Asked before the handshake is over, so held back until it is
+        engine.analyse(&game).unwrap();
+        wait_for(&mut engine, |e| e.lines().len() == 2);
+        assert_eq!(engine.name(), Some("Scripted Engine"));
+        let lines = engine.lines();
+        assert_eq!(lines[0].multipv, 1);
+        assert_eq!(lines[0].score, Some(Score::Cp(31)));
+        assert_eq!(lines[1].pv, vec!["b1c3", "g8f6"]);
+        let fen = engine.position().unwrap();
+        assert_eq!(
+            fen.line_string(&lines[0].pv, Notation::San),
+            "2. Nf3 Nc6 3. Bb5"
+        );
+        assert_eq!(engine.score(), Some(Score::Cp(31)));
+
+        // Navigating stops the search and drops its lines
+        game.back();
+        engine.analyse(&game).unwrap();
+        assert!(engine.lines().is_empty());
+        assert_eq!(engine.score(), None);
+        wait_for(&mut engine, |e| !e.lines().is_empty());
+        let lines = engine.lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].score, Some(Score::Mate(-3)));
+        let fen = engine.position().unwrap();
+        assert_eq!(
+            fen.line_string(&lines[0].pv, Notation::San),
+            "1... e5 2. Qh5"
+        );
+        assert_eq!(engine.score(), Some(Score::Mate(3)));
+    }
+}
@@ -0,0 +1,79 @@
+use enum_iterator::cardinality;
+
+use super::{Colour, Piece, BOARD_SIZE};
+
+const PIECE_CNT: usize = cardinality::<Piece>() * cardinality::<Colour>();
+const SQUARE_CNT: usize = BOARD_SIZE * BOARD_SIZE;
+
+struct Keys {
+    pieces: [[u64; SQUARE_CNT]; PIECE_CNT],
+    black_to_move: u64,
+    // White king side, white queen side, black king side, black queen side
+    castling: [u64; 4],
+    en_passant_file: [u64; BOARD_SIZE],
+}
+
+// SplitMix64, so that the keys are fixed and known at compile time
+const fn next_key(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn generate_keys() -> Keys {
+    let mut state = 0;
+    let mut pieces = [[0; SQUARE_CNT]; PIECE_CNT];
+    let mut p = 0;
+    while p < PIECE_CNT {
+        let mut sq = 0;
+        while sq < SQUARE_CNT {
+            pieces[p][sq] = next_key(&mut state);
+            sq += 1;
+        }
+        p += 1;
+    }
+    let black_to_move = next_key(&mut state);
+    let mut castling = [0; 4];
+    let mut i = 0;
+    while i < castling.len() {
+        castling[i] = next_key(&mut state);
+        i += 1;
+    }
+    let mut en_passant_file = [0; BOARD_SIZE];
+    let mut i = 0;
+    while i < BOARD_SIZE {
+        en_passant_file[i] = next_key(&mut state);
+        i += 1;
+    }
+    Keys {
+        pieces,
+        black_to_move,
+        castling,
+        en_passant_file,
+    }
+}
+
+static KEYS: Keys = generate_keys();
+
+pub fn piece(piece: Piece, colour: Colour, (rank, file): (usize, usize)) -> u64 {
+    let p = piece as usize * cardinality::<Colour>() + colour as usize;
+    KEYS.pieces[p][rank * BOARD_SIZE + file]
+}
+
+pub fn black_to_move() -> u64 {
+    KEYS.black_to_move
+}
+
+pub fn castling(rights: [bool; 4]) -> u64 {
+    rights
+        .iter()
+        .zip(KEYS.castling)
+        .filter(|(&right, _)| right)
+        .fold(0, |hash, (_, key)| hash ^ key)
+}
+
+pub fn en_passant(square: Option<(usize, usize)>) -> u64 {
+    square.map_or(0, |(_, file)| KEYS.en_passant_file[file])
+}
@@ -0,0 +1,112 @@
+use chanal_core::{Engine, EngineConfig, EngineError, Game, NodeId, Notation};
+
+use crate::{raylib::*, DimRect, FontSize, MOVES_BG_COLOUR, MOVES_FG_COLOUR};
+
+const PAD_LEFT: f32 = 10.0;
+const PAD_TOP: f32 = 10.0;
+const VSPACE: f32 = 5.0;
+// Width of the score column, in characters
+const SCORE_WIDTH: usize = 7;
+
+const ANALYSIS_BG_COLOUR: RaylibColour = RaylibColour {
+    r: 48,
+    g: 46,
+    b: 43,
+    a: 255,
+};
+
+const ANALYSIS_DIM_COLOUR: RaylibColour = RaylibColour {
+    r: 130,
+    g: 128,
+    b: 124,
+    a: 255,
+};
+
+// Shows the engine's top lines for the current position
+pub struct AnalysisPanel {
+    engine: Engine,
+    multipv: u32,
+    // Node the engine was last asked about
+    analysed: Option<NodeId>,
+    error: Option<String>,
+}
+
+impl AnalysisPanel {
+    pub fn new(config: &EngineConfig) -> Result<Self, EngineError> {
+        Ok(Self {
+            engine: Engine::spawn(config)?,
+            multipv: config.multipv.max(1),
+            analysed: None,
+            error: None,
+        })
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    // Restarts the analysis whenever the current move changes. Once the engine
+    // fails the panel only shows the error.
+    pub fn update(&mut self, game: &Game) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut res = Ok(false);
+        if self.analysed != Some(game.current()) {
+            self.analysed = Some(game.current());
+            res = self.engine.analyse(game).map(|_| true);
+        }
+        if let Err(err) = res.and_then(|_| self.engine.poll()) {
+            self.error = Some(err.to_string());
+        }
+    }
+
+    // A header, and a row for each line
+    pub fn height(&self, fs: &FontSize) -> u32 {
+        let line_height = fs.em.y + VSPACE;
+        (2.0 * PAD_TOP + line_height * (self.multipv + 1) as f32) as u32
+    }
+
+    pub fn draw(&self, ad: DimRect, font: &Font, fs: &FontSize) {
+        draw_rectangle(ad.x, ad.y, ad.width, ad.height, MOVES_BG_COLOUR);
+        draw_rectangle(ad.x, ad.y, ad.width, 1, ANALYSIS_BG_COLOUR);
+
+        let line_height = fs.em.y + VSPACE;
+        // The font is monospaced, so lines are cut to a number of characters
+        let max_chars = ((ad.width as f32 - 2.0 * PAD_LEFT) / fs.em.x).max(0.0) as usize;
+        let x = ad.x as f32 + PAD_LEFT;
+        let mut y = ad.y as f32 + PAD_TOP;
+        let mut draw_line = |text: &str, colour| {
+            let text: String = text.chars().take(max_chars).collect();
+            let pos = Vector2 { x, y };
+            draw_text_ex(&text, font, pos, fs.size, fs.spacing, colour);
+            y += line_height;
+        };
+
+        if let Some(err) = &self.error {
+            draw_line(err, ANALYSIS_DIM_COLOUR);
+            return;
+        }
+
+        let lines = self.engine.lines();
+        let name = self.engine.name().unwrap_or("Engine");
+        let header = match lines.first().and_then(|info| info.depth) {
+            Some(depth) => format!("{} - depth {}", name, depth),
+            None => name.to_string(),
+        };
+        draw_line(&header, ANALYSIS_DIM_COLOUR);
+
+        let Some(fen) = self.engine.position() else {
+            return;
+        };
+        for info in lines {
+            let score = match info.score {
+                Some(score) => score.for_white(fen.to_move()).to_string(),
+                None => String::new(),
+            };
+            let pv = fen.line_string(&info.pv, Notation::San);
+            let text = format!("{:>width$} {}", score, pv, width = SCORE_WIDTH);
+            draw_line(&text, MOVES_FG_COLOUR);
+        }
+    }
+}
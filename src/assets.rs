@@ -1,4 +1,4 @@
-use crate::chess::{Colour, Piece};
+use chanal_core::{Colour, Piece};
 
 pub static ROBOTO_MONO: &[u8] = include_bytes!("assets/RobotoMono-Medium.ttf");
 
@@ -0,0 +1,104 @@
+use chanal_core::{EvalSource, Score};
+
+use crate::{raylib::*, DimRect, FontSize};
+
+// Fraction of the gap to a new evaluation closed per second, roughly
+const EASE_RATE: f32 = 8.0;
+// Scales centipawns to white's chance of winning, as the bar shows it
+const WIN_CHANCE_SCALE: f32 = 0.00368;
+const LABEL_FONT_FRAC: f32 = 0.45;
+const LABEL_PAD: f32 = 4.0;
+
+const BAR_WHITE: RaylibColour = RaylibColour {
+    r: 238,
+    g: 238,
+    b: 238,
+    a: 255,
+};
+
+const BAR_BLACK: RaylibColour = RaylibColour {
+    r: 64,
+    g: 61,
+    b: 57,
+    a: 255,
+};
+
+const BAR_MID_COLOUR: RaylibColour = RaylibColour {
+    r: 214,
+    g: 79,
+    b: 0,
+    a: 160,
+};
+
+// A white/black bar beside the board, split by how well white stands
+pub struct EvalBar {
+    // White's share of the bar as drawn, easing towards the target
+    shown: f32,
+    target: f32,
+    score: Option<Score>,
+}
+
+impl Default for EvalBar {
+    fn default() -> Self {
+        Self {
+            shown: 0.5,
+            target: 0.5,
+            score: None,
+        }
+    }
+}
+
+impl EvalBar {
+    // Called once a frame. While the source has no score, as just after a
+    // move, the bar keeps the last one rather than jumping back to even.
+    pub fn update(&mut self, source: &impl EvalSource) {
+        if let Some(score) = source.score() {
+            self.score = Some(score);
+            self.target = white_share(score);
+        }
+        let step = 1.0 - (-EASE_RATE * get_frame_time()).exp();
+        self.shown += (self.target - self.shown) * step;
+    }
+
+    // White's part of the bar is on white's side of the board
+    pub fn draw(&self, ed: DimRect, flipped: bool, font: &Font, fs: &FontSize) {
+        let white_height = (ed.height as f32 * self.shown.clamp(0.0, 1.0)).round() as u32;
+        let black_height = ed.height - white_height;
+        let (white_y, black_y) = if flipped {
+            (ed.y, ed.y + white_height)
+        } else {
+            (ed.y + black_height, ed.y)
+        };
+        draw_rectangle(ed.x, black_y, ed.width, black_height, BAR_BLACK);
+        draw_rectangle(ed.x, white_y, ed.width, white_height, BAR_WHITE);
+        draw_rectangle(ed.x, ed.y + ed.height / 2 - 1, ed.width, 2, BAR_MID_COLOUR);
+
+        let Some(score) = self.score else {
+            return;
+        };
+        // Labelled at the end of the side that is ahead
+        let (text, white_ahead) = match score {
+            Score::Cp(cp) => (format!("{:.1}", cp.unsigned_abs() as f32 / 100.0), cp >= 0),
+            Score::Mate(n) => (format!("M{}", n.unsigned_abs()), n > 0),
+        };
+        let size = fs.size * LABEL_FONT_FRAC;
+        let spacing = fs.spacing * LABEL_FONT_FRAC;
+        let dim = measure_text_ex(&text, font, size, spacing);
+        let x = ed.x as f32 + ((ed.width as f32 - dim.x) / 2.0).max(0.0);
+        let y = if white_ahead != flipped {
+            (ed.y + ed.height) as f32 - LABEL_PAD - dim.y
+        } else {
+            ed.y as f32 + LABEL_PAD
+        };
+        let colour = if white_ahead { BAR_BLACK } else { BAR_WHITE };
+        draw_text_ex(&text, font, Vector2 { x, y }, size, spacing, colour);
+    }
+}
+
+fn white_share(score: Score) -> f32 {
+    match score {
+        Score::Cp(cp) => 1.0 / (1.0 + (-WIN_CHANCE_SCALE * cp as f32).exp()),
+        Score::Mate(n) if n > 0 => 1.0,
+        Score::Mate(_) => 0.0,
+    }
+}
@@ -1,12 +1,17 @@
 use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use assets::ROBOTO_MONO;
+use chanal_core::*;
 use itertools::iproduct;
 
-use crate::{chess::*, raylib::*};
+use crate::{analysis_panel::*, eval_bar::*, moves_panel::*, raylib::*};
 
+mod analysis_panel;
 mod assets;
-mod chess;
+mod eval_bar;
+mod moves_panel;
 mod raylib;
 
 const MARK_COLOUR: RaylibColour = RaylibColour {
@@ -67,29 +72,93 @@ fn main() {
     const TITLE: &str = "Chanal";
     const FPS: u32 = 60;
 
+    let mut args: Vec<_> = std::env::args().skip(1).collect();
+    // The engine to analyse with comes from --engine, or else the environment
+    let engine_path =
+        take_option(&mut args, "--engine").or_else(|| std::env::var("CHANAL_ENGINE").ok());
+    // The computer thinks for a second a move unless given other limits
+    let mut limits = Limits {
+        depth: take_number(&mut args, "--depth").map(|depth| depth as u32),
+        time: take_number(&mut args, "--movetime").map(Duration::from_millis),
+        nodes: take_number(&mut args, "--nodes"),
+    };
+    if limits == Limits::default() {
+        limits.time = Some(Duration::from_secs(1));
+    }
+    let pgn_path = args
+        .first()
+        .filter(|path| path.to_lowercase().ends_with(".pgn"))
+        .cloned();
+    let game = match args.first() {
+        Some(path) if path.to_lowercase().ends_with(".pgn") => {
+            let pgn = fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!("Failed to read {}: {}", path, err);
+                std::process::exit(1);
+            });
+            Game::from_pgn(&pgn).unwrap_or_else(|err| {
+                eprintln!("{}:{}", path, err);
+                std::process::exit(1);
+            })
+        }
+        Some(fen) => Game::from_fen(fen).unwrap_or_else(|err| {
+            eprintln!("Invalid FEN \"{}\": {}", fen, err);
+            std::process::exit(1);
+        }),
+        None => Game::new(),
+    };
+    let mut analysis_panel = engine_path.map(|path| {
+        AnalysisPanel::new(&EngineConfig::new(path)).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        })
+    });
+
     let mut win = Window::new(DEFAULT_WIN_WIDTH, DEFAULT_WIN_HEIGHT, TITLE);
     let _audio_dev = AudioDevice::new();
 
     let mut img_cache = ImageCache::new();
     let mut gs = GameState {
-        game: Game::new(),
+        game,
         mouse_state: MouseState::Normal,
         legal_moves: HashMap::new(),
         marked_square: None,
         to_unmark: false,
         pending_promotion: None,
+        move_input: None,
+        flipped: false,
+        computer: None,
+        pgn_path,
+        status: None,
     };
 
     let sounds = Sounds::new();
 
+    const FONT_SIZE: u32 = 25;
+    let font = Font::load_from_ttf(ROBOTO_MONO, FONT_SIZE);
+    let font_size = FontSize::from_size(&font, FONT_SIZE as f32);
+    let mut moves_panel = MovesPanel::default();
+    let mut eval_bar = EvalBar::default();
+    // Started the first time the computer is asked to play
+    let mut computer: Option<Computer> = None;
+    // Node the computer was last asked to move from
+    let mut computer_node = None;
+
     win.set_state([ConfigFlag::WindowResizable]);
     win.set_target_fps(FPS);
 
-    let sizes = Sizes::new(&win);
+    let sizes = Sizes::new(
+        &win,
+        gs.flipped,
+        analysis_panel.is_some() || gs.computer.is_some(),
+    );
     win.set_size(sizes.width, sizes.board_size);
     let mut was_resized = false;
     while !win.should_close() {
-        let sizes = Sizes::new(&win);
+        let sizes = Sizes::new(
+            &win,
+            gs.flipped,
+            analysis_panel.is_some() || gs.computer.is_some(),
+        );
         if win.is_resized() {
             was_resized = true;
         } else {
@@ -99,12 +168,75 @@ fn main() {
             was_resized = false;
         }
 
-        if gs.pending_promotion.is_some() {
+        if gs.move_input.is_some() {
+            handle_input_mode(&mut gs, &sounds);
+        } else if gs.pending_promotion.is_some() {
             handle_promotion_mode(&mut gs, sizes);
         } else {
             handle_normal_mode(&mut gs, sizes, &sounds);
         }
 
+        // Lay out the notation along-side, following clicks on moves
+        let mut md = sizes.moves_dim;
+        // The engine's lines go under the move list
+        let ad = analysis_panel.as_ref().map(|panel| {
+            let height = panel.height(&font_size).min(md.height);
+            md.height -= height;
+            DimRect {
+                y: md.y + md.height,
+                height,
+                ..md
+            }
+        });
+        let mut moves_layout = moves_panel.layout(&gs.game, &font, &font_size, md.width);
+        let clickable = gs.move_input.is_none()
+            && gs.pending_promotion.is_none()
+            && matches!(gs.mouse_state, MouseState::Normal);
+        if let Some(node) = moves_panel.update(&moves_layout, md, sizes.mouse_pos, clickable) {
+            gs.game.goto(node);
+            gs.legal_moves.clear();
+            gs.marked_square = None;
+            gs.to_unmark = false;
+            moves_layout = moves_panel.layout(&gs.game, &font, &font_size, md.width);
+        }
+
+        // The computer moves whenever it is its turn, and stops thinking once
+        // it is not
+        if let Some(side) = gs.computer {
+            let computer = computer.get_or_insert_with(Computer::new);
+            let its_turn = side == gs.game.to_move()
+                && !gs.game.status().is_over()
+                && gs.pending_promotion.is_none()
+                && gs.move_input.is_none();
+            if !its_turn {
+                computer.stop();
+                computer_node = None;
+            } else if computer_node != Some(gs.game.current()) {
+                computer.think(&gs.game, limits);
+                computer_node = Some(gs.game.current());
+            }
+            if let Some(mov) = computer.poll().filter(|_| its_turn) {
+                gs.game.apply_move(mov);
+                sounds.play_for(mov);
+                gs.legal_moves.clear();
+                gs.marked_square = None;
+                gs.to_unmark = false;
+                moves_layout = moves_panel.layout(&gs.game, &font, &font_size, md.width);
+            }
+        } else if let Some(computer) = &mut computer {
+            computer.stop();
+            computer.poll();
+            computer_node = None;
+        }
+
+        // An external engine is preferred for the evaluation
+        if let Some(panel) = &mut analysis_panel {
+            panel.update(&gs.game);
+            eval_bar.update(panel.engine());
+        } else if let Some(computer) = computer.as_ref().filter(|_| gs.computer.is_some()) {
+            eval_bar.update(computer);
+        }
+
         // Get the picked piece (if any)
         let picked_tex = if let MouseState::Picked(pp) = gs.mouse_state {
             let piece_img = img_cache.get_piece(pp.piece, pp.colour, sizes.piece_size);
@@ -118,22 +250,35 @@ fn main() {
         let board_img = img_cache.get_board(sizes.board_size);
         let board_tex = Texture2D::from(board_img);
 
+        // The dragged piece is left faded on its square, while both squares
+        // of a pending promotion are left empty
+        let picked_square = match gs.mouse_state {
+            MouseState::Picked(pp) => Some((pp.rank, pp.file)),
+            _ => None,
+        };
+        let promotion_squares = gs
+            .pending_promotion
+            .as_ref()
+            .map(|ps| [ps.mov.from(), ps.mov.to()]);
+
         let mut piece_list = Vec::new();
         let board = gs.game.board();
         for (rank, rp) in board.iter().enumerate() {
             for (file, pos) in rp.iter().enumerate() {
-                let (Position::Occupied(piece, col) | Position::Picked(piece, col)) = pos else {
+                let Position::Occupied(piece, col) = pos else {
                     continue;
                 };
-                let tint = if matches!(pos, Position::Occupied(_, _)) {
-                    WHITE
-                } else {
+                if promotion_squares.is_some_and(|sqs| sqs.contains(&(rank, file))) {
+                    continue;
+                }
+                let tint = if picked_square == Some((rank, file)) {
                     WHITE.fade(0.5)
+                } else {
+                    WHITE
                 };
                 let piece_img = img_cache.get_piece(*piece, *col, sizes.piece_size);
                 let piece_tex = Texture2D::from(piece_img);
-                let xpos = sizes.boardx + file as u32 * sizes.piece_size;
-                let ypos = sizes.boardy + rank as u32 * sizes.piece_size;
+                let (xpos, ypos) = sizes.square_pos((rank, file));
                 piece_list.push((piece_tex, xpos, ypos, tint));
             }
         }
@@ -154,8 +299,7 @@ fn main() {
         let orange_tex = Texture2D::from(img_cache.get_orange_sq(sizes.piece_size));
         if let Some(ps) = &gs.pending_promotion {
             for (&(r, f), pp) in &ps.pieces {
-                let xpos = sizes.boardx + f as u32 * sizes.piece_size;
-                let ypos = sizes.boardy + r as u32 * sizes.piece_size;
+                let (xpos, ypos) = sizes.square_pos((r, f));
                 let piece_img = img_cache.get_piece(pp.piece, pp.colour, sizes.piece_size);
                 let piece_tex = Texture2D::from(piece_img);
                 let highlight_tex = if pp.highlighted {
@@ -186,36 +330,26 @@ fn main() {
             None
         };
 
-        // For notation along-side
-        let game_moves = gs.game.game_moves();
-        const FONT_SIZE: u32 = 25;
-        let font = Font::load_from_ttf(ROBOTO_MONO, FONT_SIZE);
-        let font_size = FontSize::from_size(&font, FONT_SIZE as f32);
-
         raylib::do_draw(|| {
             raylib::clear_background(WHITE);
 
             // Draw board and pieces
             board_tex.draw(sizes.boardx, sizes.boardy, WHITE);
-            if let Some((rank, file)) = gs.marked_square {
-                let x = sizes.boardx + file as u32 * sizes.piece_size;
-                let y = sizes.boardy + rank as u32 * sizes.piece_size;
+            if let Some(square) = gs.marked_square {
+                let (x, y) = sizes.square_pos(square);
                 draw_rectangle(x, y, sizes.piece_size, sizes.piece_size, MARK_COLOUR);
             }
             if let Some(check_tex) = &check_tex {
-                let (kr, kf) = gs.game.king_position();
-                let x = sizes.boardx + kf as u32 * sizes.piece_size;
-                let y = sizes.boardy + kr as u32 * sizes.piece_size;
+                let (x, y) = sizes.square_pos(gs.game.king_position());
                 check_tex.draw(x, y, WHITE);
             }
-            for (&(rank, file), mov) in &gs.legal_moves {
+            for (&square, mov) in &gs.legal_moves {
+                let (x, y) = sizes.square_pos(square);
                 if mov.has_capture() {
-                    let x = sizes.boardx + file as u32 * sizes.piece_size;
-                    let y = sizes.boardy + rank as u32 * sizes.piece_size;
                     cap_tex.draw(x, y, WHITE);
                 } else {
-                    let x = sizes.boardx + file as u32 * sizes.piece_size + sizes.piece_size / 2;
-                    let y = sizes.boardy + rank as u32 * sizes.piece_size + sizes.piece_size / 2;
+                    let x = x + sizes.piece_size / 2;
+                    let y = y + sizes.piece_size / 2;
                     let radius = sizes.piece_size as f32 / 5.0;
                     draw_circle(x, y, radius, MARK_COLOUR);
                 }
@@ -242,48 +376,121 @@ fn main() {
             }
 
             // Draw moves
-            let md = sizes.moves_dim;
-            draw_rectangle(md.x, md.y, md.width, md.height, MOVES_BG_COLOUR);
-
-            const MOVE_PAD_LEFT: f32 = 10.0;
-            const MOVE_PAD_TOP: f32 = 20.0;
-            const MOVE_GAP_CNT: f32 = 10.0;
-            const MOVE_NUM_GAP_CNT: f32 = 3.5;
-            const MOVE_VSPACE: f32 = 5.0;
-            let move_gap = font_size.em.x * MOVE_GAP_CNT;
-            let move_num_gap = font_size.em.x * MOVE_NUM_GAP_CNT;
-
-            for (turn_num, turn) in game_moves.chunks(2).enumerate() {
-                let turn_str = format!("{}", turn_num + 1);
-                let y = MOVE_PAD_TOP + (MOVE_VSPACE + font_size.em.y) * turn_num as f32;
-                let num_x = md.x as f32 + MOVE_PAD_LEFT;
-                let white_x = num_x + move_num_gap;
-                let (texts, positions) = if turn.len() == 2 {
-                    let black_x = white_x + move_gap;
-                    let texts = vec![&turn_str, &turn[0].notation, &turn[1].notation];
-                    let positions = vec![num_x, white_x, black_x];
-                    (texts, positions)
-                } else {
-                    let texts = vec![&turn_str, &turn[0].notation];
-                    let positions = vec![num_x, white_x];
-                    (texts, positions)
-                };
-                for (t, p) in texts.into_iter().zip(positions) {
-                    let pos = Vector2 { x: p, y };
+            moves_panel.draw(&moves_layout, md, &font, &font_size);
+            if let (Some(panel), Some(ad)) = (&analysis_panel, ad) {
+                panel.draw(ad, &font, &font_size);
+            }
+            if sizes.eval_dim.width > 0 {
+                eval_bar.draw(sizes.eval_dim, gs.flipped, &font, &font_size);
+            }
+
+            if let Some(input) = &gs.move_input {
+                const INPUT_PAD_LEFT: f32 = 10.0;
+                const INPUT_VSPACE: f32 = 5.0;
+                let line_height = INPUT_VSPACE + font_size.em.y;
+                let x = md.x as f32 + INPUT_PAD_LEFT;
+                let mut y = md.height as f32 - line_height;
+
+                // The prompt covers the bottom of the move list
+                let lines = if input.error.is_some() { 2.0 } else { 1.0 };
+                let top = (md.height as f32 - line_height * lines - INPUT_VSPACE) as u32;
+                draw_rectangle(md.x, top, md.width, md.height - top, MOVES_BG_COLOUR);
+
+                if let Some(err) = &input.error {
+                    let pos = Vector2 { x, y };
                     draw_text_ex(
-                        t.as_str(),
+                        err,
                         &font,
                         pos,
                         font_size.size,
                         font_size.spacing,
-                        MOVES_FG_COLOUR,
+                        CHECK_RED,
                     );
+                    y -= line_height;
                 }
+                let pos = Vector2 { x, y };
+                draw_text_ex(
+                    &format!("{}{}", if input.is_comment { ';' } else { ':' }, input.text),
+                    &font,
+                    pos,
+                    font_size.size,
+                    font_size.spacing,
+                    MOVES_FG_COLOUR,
+                );
+            } else if let Some(status) = &gs.status {
+                const STATUS_PAD_LEFT: f32 = 10.0;
+                const STATUS_VSPACE: f32 = 5.0;
+                let line_height = STATUS_VSPACE + font_size.em.y;
+                let top = (md.height as f32 - line_height - STATUS_VSPACE) as u32;
+                draw_rectangle(md.x, top, md.width, md.height - top, MOVES_BG_COLOUR);
+
+                let pos = Vector2 {
+                    x: md.x as f32 + STATUS_PAD_LEFT,
+                    y: md.height as f32 - line_height,
+                };
+                let colour = if status.is_error {
+                    CHECK_RED
+                } else {
+                    MOVES_FG_COLOUR
+                };
+                draw_text_ex(
+                    &status.text,
+                    &font,
+                    pos,
+                    font_size.size,
+                    font_size.spacing,
+                    colour,
+                );
             }
         })
     }
 }
 
+fn handle_input_mode(gs: &mut GameState, sounds: &Sounds) {
+    let input = gs.move_input.as_mut().unwrap();
+    loop {
+        let ch = get_char_pressed();
+        if ch == '\0' {
+            break;
+        }
+        input.text.push(ch);
+        input.error = None;
+    }
+
+    if is_key_released(Key::Backspace) {
+        input.text.pop();
+        input.error = None;
+    } else if is_key_released(Key::Escape) {
+        gs.move_input = None;
+        set_exit_key(Key::Q);
+    } else if is_key_released(Key::Enter) && input.is_comment && input.text.contains('}') {
+        // PGN comments cannot hold it
+        input.error = Some("Comments cannot contain }".to_string());
+    } else if is_key_released(Key::Enter) && input.is_comment {
+        let text = input.text.trim();
+        gs.game.annotation_mut().comment_after = Some(text.to_string()).filter(|t| !t.is_empty());
+        gs.move_input = None;
+        set_exit_key(Key::Q);
+    } else if is_key_released(Key::Enter) && gs.computer == Some(gs.game.to_move()) {
+        input.error = Some("The computer plays this side".to_string());
+    } else if is_key_released(Key::Enter) {
+        let text = input.text.trim();
+        let parsed = gs
+            .game
+            .parse_san(text)
+            .or_else(|err| gs.game.parse_uci(text).map_err(|_| err));
+        match parsed {
+            Ok(mov) => {
+                gs.game.apply_move(mov);
+                sounds.play_for(mov);
+                gs.move_input = None;
+                set_exit_key(Key::Q);
+            }
+            Err(err) => input.error = Some(err.to_string()),
+        }
+    }
+}
+
 fn handle_promotion_mode(gs: &mut GameState, sizes: Sizes) {
     let board_rect = Rectangle {
         x: sizes.boardx as _,
@@ -297,10 +504,7 @@ fn handle_promotion_mode(gs: &mut GameState, sizes: Sizes) {
         return;
     }
 
-    let mx = sizes.mouse_pos.x as u32;
-    let my = sizes.mouse_pos.y as u32;
-    let file = ((mx - sizes.boardx) / sizes.piece_size) as usize;
-    let rank = ((my - sizes.boardy) / sizes.piece_size) as usize;
+    let (rank, file) = sizes.mouse_square();
 
     let ps = gs.pending_promotion.as_mut().unwrap();
     for pp in ps.pieces.values_mut() {
@@ -311,13 +515,67 @@ fn handle_promotion_mode(gs: &mut GameState, sizes: Sizes) {
         set_mouse_cursor(MouseCursor::PointingHand);
 
         if is_mouse_button_pressed(MouseButton::Left) {
-            ps.mov.set_promotion(pp.piece, pp.colour);
-            gs.game.apply_move(ps.mov);
+            let mov = gs
+                .game
+                .all_legal_moves()
+                .into_iter()
+                .find(|m| {
+                    m.from() == ps.mov.from()
+                        && m.to() == ps.mov.to()
+                        && m.promotion() == Some(pp.piece)
+                })
+                .unwrap();
+            gs.game.apply_move(mov);
             gs.pending_promotion = None;
         }
     }
 }
 
+// Removes `<name> <value>` from the arguments, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let idx = args.iter().position(|arg| arg == name)?;
+    if idx + 1 == args.len() {
+        eprintln!("Missing value for {}", name);
+        std::process::exit(1);
+    }
+    args.remove(idx);
+    Some(args.remove(idx))
+}
+
+fn take_number(args: &mut Vec<String>, name: &str) -> Option<u64> {
+    let value = take_option(args, name)?;
+    let num = value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value for {}: {}", name, value);
+        std::process::exit(1);
+    });
+    Some(num)
+}
+
+// Saves back to the PGN the game came from, or else to a new file which later
+// saves then go to
+fn save_pgn(gs: &mut GameState) {
+    let path = gs.pgn_path.clone().unwrap_or_else(|| {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        format!("chanal-{}.pgn", secs)
+    });
+    gs.status = Some(match fs::write(&path, gs.game.to_pgn()) {
+        Ok(()) => {
+            let text = format!("Saved game to {}", path);
+            gs.pgn_path = Some(path);
+            Status {
+                text,
+                is_error: false,
+            }
+        }
+        Err(err) => Status {
+            text: format!("Failed to save game to {}: {}", path, err),
+            is_error: true,
+        },
+    });
+}
+
 fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
     // Set cursor
     let board_rect = Rectangle {
@@ -337,13 +595,11 @@ fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
     if is_mouse_on_board {
         if is_mouse_button_down(MouseButton::Left) {
             if let MouseState::Normal = gs.mouse_state {
-                let mx = sizes.mouse_pos.x as u32;
-                let my = sizes.mouse_pos.y as u32;
-                let file = ((mx - sizes.boardx) / sizes.piece_size) as usize;
-                let rank = ((my - sizes.boardy) / sizes.piece_size) as usize;
+                let (rank, file) = sizes.mouse_square();
                 let pos = gs.game.board()[rank][file];
                 if let Position::Occupied(piece, colour) = pos {
-                    if colour == gs.game.to_move() {
+                    let is_players = colour == gs.game.to_move() && gs.computer != Some(colour);
+                    if is_players && !gs.game.status().is_over() {
                         let pp = PickedPiece {
                             piece,
                             colour,
@@ -351,7 +607,6 @@ fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
                             file,
                         };
                         gs.mouse_state = MouseState::Picked(pp);
-                        gs.game.board_mut()[rank][file] = Position::Picked(piece, colour);
                         gs.legal_moves = gs.game.legal_moves(rank, file);
                         if let Some((or, of)) = gs.marked_square {
                             gs.to_unmark = or == rank && of == file;
@@ -372,19 +627,13 @@ fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
                 MouseState::Clicked => {
                     gs.mouse_state = MouseState::Normal;
                 }
-                MouseState::Picked(pp) => {
-                    let mx = sizes.mouse_pos.x as u32;
-                    let my = sizes.mouse_pos.y as u32;
-                    let file = ((mx - sizes.boardx) / sizes.piece_size) as usize;
-                    let rank = ((my - sizes.boardy) / sizes.piece_size) as usize;
+                MouseState::Picked(_) => {
+                    let (rank, file) = sizes.mouse_square();
 
                     gs.mouse_state = MouseState::Normal;
                     if let Some(&mov) = gs.legal_moves.get(&(rank, file)) {
                         if mov.may_promote() {
                             gs.pending_promotion = Some(PromotionState::new(mov));
-                            let board = gs.game.board_mut();
-                            board[pp.rank][pp.file] = Position::Empty;
-                            board[rank][file] = Position::Empty;
                         } else {
                             gs.game.apply_move(mov);
                         }
@@ -392,16 +641,7 @@ fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
                         gs.legal_moves.clear();
                         gs.to_unmark = false;
 
-                        if mov.has_check() {
-                            sounds.check_sound.play();
-                        } else if mov.has_capture() {
-                            sounds.capture_sound.play();
-                        } else {
-                            sounds.move_sound.play();
-                        }
-                    } else {
-                        gs.game.board_mut()[pp.rank][pp.file] =
-                            Position::Occupied(pp.piece, pp.colour);
+                        sounds.play_for(mov);
                     }
 
                     if gs.to_unmark {
@@ -414,28 +654,72 @@ fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
 
         // Handle keyboard presses when piece is not picked
         if matches!(gs.mouse_state, MouseState::Normal) {
-            if get_char_pressed() != '\0' {
+            let ch = get_char_pressed();
+            if ch != '\0' {
                 gs.legal_moves.clear();
                 gs.marked_square = None;
                 gs.to_unmark = false;
+                gs.status = None;
+            }
+            if ch == ':' {
+                // Typed moves may contain Q, so it must not close the window meanwhile
+                set_exit_key(Key::Null);
+                gs.move_input = Some(MoveInput::default());
+                return;
             }
-            if is_key_released(Key::J) {
+            if ch == ';' && !gs.game.at_start() {
+                set_exit_key(Key::Null);
+                gs.move_input = Some(MoveInput {
+                    text: gs
+                        .game
+                        .annotation()
+                        .comment_after
+                        .clone()
+                        .unwrap_or_default(),
+                    error: None,
+                    is_comment: true,
+                });
+                return;
+            }
+            if let Some(d) = ch.to_digit(10).filter(|d| (1..=6).contains(d)) {
+                if !gs.game.at_start() {
+                    gs.game.annotation_mut().toggle_nag(Nag(d as u8));
+                }
+            } else if is_key_released(Key::J) {
                 gs.game.back();
             } else if is_key_released(Key::K) {
                 let mov = gs.game.forward();
                 if let Some(mov) = mov {
-                    if mov.has_check() {
-                        sounds.check_sound.play();
-                    } else if mov.has_capture() {
-                        sounds.capture_sound.play();
-                    } else {
-                        sounds.move_sound.play();
-                    }
+                    sounds.play_for(mov);
                 }
             } else if is_key_released(Key::H) {
                 gs.game.prev_variation();
             } else if is_key_released(Key::L) {
                 gs.game.next_variation();
+            } else if is_key_released(Key::R) {
+                gs.flipped = !gs.flipped;
+            } else if is_key_released(Key::C) {
+                // The computer takes over the side to move, or stops playing
+                gs.computer = match gs.computer {
+                    Some(_) => None,
+                    None => Some(gs.game.to_move()),
+                };
+            } else if is_key_released(Key::S) {
+                save_pgn(gs);
+            } else if is_key_released(Key::D) {
+                gs.game.delete_variation();
+            } else if is_key_released(Key::P) {
+                gs.game.promote_variation_to_mainline();
+            } else if is_key_released(Key::T) {
+                gs.game.truncate_after_current();
+            } else if is_key_released(Key::Home) {
+                gs.game.goto_start();
+            } else if is_key_released(Key::End) {
+                gs.game.goto_end();
+            } else if is_key_released(Key::Up) {
+                gs.game.reorder_variation(true);
+            } else if is_key_released(Key::Down) {
+                gs.game.reorder_variation(false);
             }
         }
     }
@@ -443,7 +727,6 @@ fn handle_normal_mode(gs: &mut GameState, sizes: Sizes, sounds: &Sounds) {
 
 #[derive(Copy, Clone, Debug)]
 struct Sizes {
-    height: u32,
     width: u32,
     boardx: u32,
     boardy: u32,
@@ -451,19 +734,21 @@ struct Sizes {
     piece_size: u32,
     mouse_pos: Vector2,
     moves_dim: DimRect,
+    eval_dim: DimRect,
+    // Black is at the bottom of the board
+    flipped: bool,
 }
 
 impl Sizes {
-    fn new(win: &Window) -> Self {
+    fn new(win: &Window, flipped: bool, eval_bar: bool) -> Self {
         let (width, height) = win.size();
-        let (board_dim, moves_dim) = board_and_moves_dim(width, height);
+        let (board_dim, eval_dim, moves_dim) = board_and_moves_dim(width, height, eval_bar);
         let (boardx, boardy) = (board_dim.x, board_dim.y);
         let board_size = board_dim.width;
-        let piece_size = board_size / chess::BOARD_SIZE as u32;
+        let piece_size = board_size / BOARD_SIZE as u32;
         let mouse_pos = get_mouse_position();
 
         Self {
-            height,
             width,
             boardx,
             boardy,
@@ -471,6 +756,32 @@ impl Sizes {
             piece_size,
             mouse_pos,
             moves_dim,
+            eval_dim,
+            flipped,
+        }
+    }
+
+    // Top left corner of the square on the screen
+    fn square_pos(&self, (rank, file): (usize, usize)) -> (u32, u32) {
+        let (rank, file) = self.orient((rank, file));
+        let x = self.boardx + file as u32 * self.piece_size;
+        let y = self.boardy + rank as u32 * self.piece_size;
+        (x, y)
+    }
+
+    // The square under the mouse, which must be on the board
+    fn mouse_square(&self) -> (usize, usize) {
+        let file = ((self.mouse_pos.x as u32 - self.boardx) / self.piece_size) as usize;
+        let rank = ((self.mouse_pos.y as u32 - self.boardy) / self.piece_size) as usize;
+        self.orient((rank, file))
+    }
+
+    // Turning the board round is its own inverse
+    fn orient(&self, (rank, file): (usize, usize)) -> (usize, usize) {
+        if self.flipped {
+            (BOARD_SIZE - 1 - rank, BOARD_SIZE - 1 - file)
+        } else {
+            (rank, file)
         }
     }
 }
@@ -496,6 +807,16 @@ impl Sounds {
             check_sound,
         }
     }
+
+    fn play_for(&self, mov: Move) {
+        if mov.has_check() {
+            self.check_sound.play();
+        } else if mov.has_capture() {
+            self.capture_sound.play();
+        } else {
+            self.move_sound.play();
+        }
+    }
 }
 
 struct ImageCache {
@@ -516,7 +837,7 @@ impl ImageCache {
         assert!(bw == bh);
         let boards = HashMap::from([(bw, board_img)]);
 
-        let piece_size = bw / chess::BOARD_SIZE as u32;
+        let piece_size = bw / BOARD_SIZE as u32;
         let pieces = iproduct!(
             enum_iterator::all::<Colour>(),
             enum_iterator::all::<Piece>()
@@ -617,17 +938,25 @@ struct DimRect {
     height: u32,
 }
 
-fn board_and_moves_dim(width: u32, height: u32) -> (DimRect, DimRect) {
+// The evaluation bar, if any, sits between the board and the moves
+fn board_and_moves_dim(width: u32, height: u32, eval_bar: bool) -> (DimRect, DimRect, DimRect) {
     const BOARD_FRAC: f32 = 0.65;
+    const EVAL_FRAC: f32 = 0.04;
     let mut board_dim = DimRect::default();
+    let mut eval_dim = DimRect::default();
     let mut moves_dim = DimRect::default();
     let mut board_size = height.min((width as f32 * BOARD_FRAC) as u32);
-    board_size = board_size - board_size % chess::BOARD_SIZE as u32;
+    board_size = board_size - board_size % BOARD_SIZE as u32;
     (board_dim.width, board_dim.height) = (board_size, board_size);
-    moves_dim.x = board_size;
-    moves_dim.width = width - board_size;
+    eval_dim.x = board_size;
+    if eval_bar {
+        eval_dim.width = ((board_size as f32 * EVAL_FRAC) as u32).min(width - board_size);
+        eval_dim.height = board_size;
+    }
+    moves_dim.x = eval_dim.x + eval_dim.width;
+    moves_dim.width = width - moves_dim.x;
     moves_dim.height = height;
-    (board_dim, moves_dim)
+    (board_dim, eval_dim, moves_dim)
 }
 
 #[derive(Debug)]
@@ -638,6 +967,28 @@ struct GameState {
     marked_square: Option<(usize, usize)>,
     to_unmark: bool,
     pending_promotion: Option<PromotionState>,
+    move_input: Option<MoveInput>,
+    flipped: bool,
+    // The side the computer plays, if it is playing
+    computer: Option<Colour>,
+    // Where the game is saved to
+    pgn_path: Option<String>,
+    // Shown under the moves until the next key press
+    status: Option<Status>,
+}
+
+#[derive(Debug)]
+struct Status {
+    text: String,
+    is_error: bool,
+}
+
+#[derive(Debug, Default)]
+struct MoveInput {
+    text: String,
+    error: Option<String>,
+    // Editing the comment on the current move rather than typing a move
+    is_comment: bool,
 }
 
 #[derive(Debug)]
@@ -669,13 +1020,17 @@ struct PromotionPiece {
 }
 
 impl PromotionState {
+    // The picker runs from the promotion square towards the middle of the
+    // board. Its squares are turned with the rest of the board when flipped.
     fn new(mov: Move) -> Self {
         const PIECES: [Piece; 4] = [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop];
+        let (to_rank, to_file) = mov.to();
         let pieces = PIECES
             .into_iter()
             .enumerate()
             .map(|(i, p)| {
-                let pos = (i, mov.to().1);
+                let rank = if to_rank == 0 { i } else { to_rank - i };
+                let pos = (rank, to_file);
                 let pp = PromotionPiece {
                     piece: p,
                     colour: mov.colour(),
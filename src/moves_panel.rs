@@ -0,0 +1,459 @@
+use std::collections::HashSet;
+
+use chanal_core::{Colour, Game, GameMove, NodeId};
+
+use crate::{raylib::*, DimRect, FontSize, MOVES_BG_COLOUR, MOVES_FG_COLOUR};
+
+const PAD_LEFT: f32 = 10.0;
+const PAD_TOP: f32 = 20.0;
+const MOVE_GAP_CNT: f32 = 10.0;
+const MOVE_NUM_GAP_CNT: f32 = 3.5;
+const VSPACE: f32 = 5.0;
+const VARIATION_INDENT_CNT: f32 = 2.0;
+// Lines scrolled per notch of the mouse wheel
+const WHEEL_LINES: f32 = 3.0;
+
+const MOVES_COMMENT_COLOUR: RaylibColour = RaylibColour {
+    r: 120,
+    g: 160,
+    b: 110,
+    a: 255,
+};
+
+// Variations are coloured by how deeply they are nested
+const VARIATION_COLOURS: [RaylibColour; 3] = [
+    RaylibColour {
+        r: 140,
+        g: 170,
+        b: 205,
+        a: 255,
+    },
+    RaylibColour {
+        r: 205,
+        g: 170,
+        b: 120,
+        a: 255,
+    },
+    RaylibColour {
+        r: 175,
+        g: 145,
+        b: 200,
+        a: 255,
+    },
+];
+
+const CURR_MOVE_BG_COLOUR: RaylibColour = RaylibColour {
+    r: 75,
+    g: 72,
+    b: 68,
+    a: 255,
+};
+
+#[derive(Clone, Copy)]
+enum Action {
+    Goto(NodeId),
+    // Collapse or expand the variation starting with the node
+    Toggle(NodeId),
+}
+
+struct Item {
+    text: String,
+    // Relative to the left of the panel and the top of the move list
+    pos: Vector2,
+    width: f32,
+    colour: RaylibColour,
+    action: Option<Action>,
+    is_curr: bool,
+}
+
+pub struct MovesLayout {
+    header: Option<String>,
+    // Other moves leading to the current position
+    footer: Option<String>,
+    items: Vec<Item>,
+    height: f32,
+    line_height: f32,
+}
+
+impl MovesLayout {
+    // The part of the panel the move list scrolls within
+    fn view(&self, md: DimRect) -> Rectangle {
+        let mut top = PAD_TOP;
+        if self.header.is_some() {
+            top += self.line_height * 1.5;
+        }
+        let mut bottom = PAD_TOP;
+        if self.footer.is_some() {
+            bottom += self.line_height;
+        }
+        Rectangle {
+            x: md.x as f32,
+            y: md.y as f32 + top,
+            width: md.width as f32,
+            height: (md.height as f32 - top - bottom).max(0.0),
+        }
+    }
+}
+
+// Places the main line in rows of a white and a black move, with variations
+// as indented paragraphs beneath the move they are alternatives to
+struct LayoutBuilder<'a> {
+    game: &'a Game,
+    font: &'a Font,
+    fs: &'a FontSize,
+    collapsed: &'a HashSet<NodeId>,
+    // Variations on the way to the current move are never collapsed
+    path: HashSet<NodeId>,
+    width: f32,
+    line_height: f32,
+    items: Vec<Item>,
+    x: f32,
+    y: f32,
+    // No space before the next token, as after an opening parenthesis
+    glue: bool,
+}
+
+impl LayoutBuilder<'_> {
+    fn push(&mut self, text: String, x: f32, colour: RaylibColour) -> &mut Item {
+        let width = measure_text_ex(&text, self.font, self.fs.size, self.fs.spacing).x;
+        self.items.push(Item {
+            text,
+            pos: Vector2 { x, y: self.y },
+            width,
+            colour,
+            action: None,
+            is_curr: false,
+        });
+        self.items.last_mut().unwrap()
+    }
+
+    // Places the token after the previous one, wrapping back to `indent`
+    fn flow(&mut self, text: String, indent: f32, colour: RaylibColour) -> &mut Item {
+        let width = measure_text_ex(&text, self.font, self.fs.size, self.fs.spacing).x;
+        let mut x = self.x;
+        if x > indent && !self.glue {
+            x += self.fs.em.x;
+        }
+        if x > indent && x + width > self.width - PAD_LEFT {
+            self.y += self.line_height;
+            x = indent;
+        }
+        self.x = x + width;
+        self.glue = false;
+        self.push(text, x, colour)
+    }
+
+    fn flow_comment(&mut self, comment: &str, indent: f32) {
+        for word in comment.split_whitespace() {
+            self.flow(word.to_string(), indent, MOVES_COMMENT_COLOUR);
+        }
+    }
+
+    // Returns whether the move after this one needs its number repeated
+    fn flow_move(
+        &mut self,
+        gm: &GameMove,
+        mut force_number: bool,
+        indent: f32,
+        colour: RaylibColour,
+    ) -> bool {
+        if let Some(comment) = &gm.annotation.comment_before {
+            self.flow_comment(comment, indent);
+            force_number = true;
+        }
+        match gm.mov.colour() {
+            Colour::White => {
+                self.flow(format!("{}.", gm.move_num), indent, colour);
+            }
+            Colour::Black if force_number => {
+                self.flow(format!("{}...", gm.move_num), indent, colour);
+            }
+            Colour::Black => {}
+        }
+        let item = self.flow(annotated_notation(gm), indent, colour);
+        item.action = Some(Action::Goto(gm.node));
+        item.is_curr = gm.is_curr;
+        if let Some(comment) = &gm.annotation.comment_after {
+            self.flow_comment(comment, indent);
+            return true;
+        }
+        false
+    }
+
+    fn main_line(&mut self) {
+        let white_x = PAD_LEFT + self.fs.em.x * MOVE_NUM_GAP_CNT;
+        let black_x = white_x + self.fs.em.x * MOVE_GAP_CNT;
+        let mut row_open = false;
+        let mut parent = self.game.root();
+        for gm in self.game.game_moves() {
+            let move_num = gm.move_num.to_string();
+            let x = match gm.mov.colour() {
+                Colour::White => {
+                    self.push(move_num, PAD_LEFT, MOVES_FG_COLOUR);
+                    white_x
+                }
+                Colour::Black if row_open => black_x,
+                Colour::Black => {
+                    self.push(move_num, PAD_LEFT, MOVES_FG_COLOUR);
+                    self.push("...".to_string(), white_x, MOVES_FG_COLOUR);
+                    black_x
+                }
+            };
+            let item = self.push(annotated_notation(&gm), x, MOVES_FG_COLOUR);
+            item.action = Some(Action::Goto(gm.node));
+            item.is_curr = gm.is_curr;
+            row_open = gm.mov.colour() == Colour::White;
+            if !row_open {
+                self.y += self.line_height;
+            }
+
+            let sides: Vec<_> = self
+                .game
+                .continuations(parent)
+                .into_iter()
+                .skip(1)
+                .collect();
+            let annotation = &gm.annotation;
+            let comments = annotation
+                .comment_before
+                .iter()
+                .chain(&annotation.comment_after);
+            if row_open && (comments.clone().count() > 0 || !sides.is_empty()) {
+                self.y += self.line_height;
+                row_open = false;
+            }
+            for comment in comments {
+                self.x = white_x;
+                self.flow_comment(comment, white_x);
+                self.y += self.line_height;
+            }
+            for side in &sides {
+                self.variation(side, 1);
+            }
+            parent = gm.node;
+        }
+        if row_open {
+            self.y += self.line_height;
+        }
+    }
+
+    fn variation(&mut self, first: &GameMove, depth: usize) {
+        let indent = PAD_LEFT + self.fs.em.x * VARIATION_INDENT_CNT * depth as f32;
+        let colour = VARIATION_COLOURS[(depth - 1) % VARIATION_COLOURS.len()];
+        let expanded = !self.collapsed.contains(&first.node) || self.path.contains(&first.node);
+        self.x = indent;
+        let marker = if expanded { "[-]" } else { "[+]" };
+        self.flow(marker.to_string(), indent, colour).action = Some(Action::Toggle(first.node));
+        self.flow("(".to_string(), indent, colour);
+        self.glue = true;
+        let mut force_number = self.flow_move(first, true, indent, colour);
+        if !expanded {
+            self.flow("...)".to_string(), indent, colour).action = Some(Action::Toggle(first.node));
+            self.y += self.line_height;
+            return;
+        }
+
+        let mut curr = first.node;
+        loop {
+            let continuations = self.game.continuations(curr);
+            let Some((main, sides)) = continuations.split_first() else {
+                break;
+            };
+            force_number = self.flow_move(main, force_number, indent, colour);
+            for side in sides {
+                self.y += self.line_height;
+                self.variation(side, depth + 1);
+                self.x = indent;
+                force_number = true;
+            }
+            curr = main.node;
+        }
+        self.glue = true;
+        self.flow(")".to_string(), indent, colour);
+        self.y += self.line_height;
+    }
+}
+
+// Move assessments are written straight after the move, anything else apart
+fn annotated_notation(gm: &GameMove) -> String {
+    let mut text = gm.notation.clone();
+    for nag in &gm.annotation.nags {
+        if !nag.is_move_assessment() {
+            text.push(' ');
+        }
+        text.push_str(&nag.to_string());
+    }
+    text
+}
+
+#[derive(Debug, Default)]
+pub struct MovesPanel {
+    scroll: f32,
+    // The current move when the panel was last scrolled to show it
+    followed: Option<NodeId>,
+    // First moves of the variations shown collapsed
+    collapsed: HashSet<NodeId>,
+}
+
+impl MovesPanel {
+    pub fn layout(&self, game: &Game, font: &Font, fs: &FontSize, width: u32) -> MovesLayout {
+        let header = game
+            .tag("White")
+            .zip(game.tag("Black"))
+            .map(|(white, black)| format!("{} - {}", white, black));
+        let line_height = VSPACE + fs.em.y;
+        let mut builder = LayoutBuilder {
+            game,
+            font,
+            fs,
+            collapsed: &self.collapsed,
+            path: game.path_to_current().into_iter().collect(),
+            width: width as f32,
+            line_height,
+            items: Vec::new(),
+            x: 0.0,
+            y: 0.0,
+            glue: false,
+        };
+        builder.main_line();
+
+        let status = game.status();
+        if status.is_over() {
+            builder.push(status.to_string(), PAD_LEFT, MOVES_FG_COLOUR);
+            builder.y += line_height;
+        }
+
+        let transpositions: Vec<_> = game
+            .transpositions()
+            .iter()
+            .map(|gm| match gm.mov.colour() {
+                Colour::White => format!("{}.{}", gm.move_num, gm.notation),
+                Colour::Black => format!("{}...{}", gm.move_num, gm.notation),
+            })
+            .collect();
+        let footer = (!transpositions.is_empty()).then(|| {
+            let text = format!("Also after {}", transpositions.join(", "));
+            // Monospace font, so the text can be cut to the panel width by length
+            let max_chars = ((width as f32 - 2.0 * PAD_LEFT) / fs.em.x) as usize;
+            text.chars().take(max_chars).collect()
+        });
+
+        MovesLayout {
+            header,
+            footer,
+            height: builder.y,
+            items: builder.items,
+            line_height,
+        }
+    }
+
+    // Scrolls with the mouse wheel and keeps the current move in view when it
+    // changes. Returns the move clicked on, if any.
+    pub fn update(
+        &mut self,
+        layout: &MovesLayout,
+        md: DimRect,
+        mouse_pos: Vector2,
+        clickable: bool,
+    ) -> Option<NodeId> {
+        let view = layout.view(md);
+        let curr = layout.items.iter().find(|item| item.is_curr);
+        let curr_node = curr.and_then(|item| match item.action {
+            Some(Action::Goto(node)) => Some(node),
+            _ => None,
+        });
+        if curr_node != self.followed {
+            self.followed = curr_node;
+            match curr {
+                Some(item) if item.pos.y < self.scroll => self.scroll = item.pos.y,
+                Some(item) if item.pos.y + layout.line_height > self.scroll + view.height => {
+                    self.scroll = item.pos.y + layout.line_height - view.height;
+                }
+                Some(_) => {}
+                None => self.scroll = 0.0,
+            }
+        }
+
+        let is_mouse_on_view = check_collision_point_rect(mouse_pos, view);
+        if is_mouse_on_view {
+            self.scroll -= get_mouse_wheel_move() * WHEEL_LINES * layout.line_height;
+        }
+        let max_scroll = (layout.height - view.height).max(0.0);
+        self.scroll = self.scroll.clamp(0.0, max_scroll);
+
+        if !clickable || !is_mouse_on_view {
+            return None;
+        }
+        let hovered = layout.items.iter().find(|item| {
+            item.action.is_some()
+                && check_collision_point_rect(mouse_pos, self.item_rect(layout, item, view))
+        })?;
+        set_mouse_cursor(MouseCursor::PointingHand);
+        if !is_mouse_button_pressed(MouseButton::Left) {
+            return None;
+        }
+        match hovered.action? {
+            Action::Goto(node) => Some(node),
+            Action::Toggle(node) => {
+                if !self.collapsed.remove(&node) {
+                    self.collapsed.insert(node);
+                }
+                None
+            }
+        }
+    }
+
+    fn item_rect(&self, layout: &MovesLayout, item: &Item, view: Rectangle) -> Rectangle {
+        Rectangle {
+            x: view.x + item.pos.x - PAD_LEFT / 2.0,
+            y: view.y + item.pos.y - self.scroll - VSPACE / 2.0,
+            width: item.width + PAD_LEFT,
+            height: layout.line_height,
+        }
+    }
+
+    pub fn draw(&self, layout: &MovesLayout, md: DimRect, font: &Font, fs: &FontSize) {
+        draw_rectangle(md.x, md.y, md.width, md.height, MOVES_BG_COLOUR);
+
+        if let Some(header) = &layout.header {
+            let pos = Vector2 {
+                x: md.x as f32 + PAD_LEFT,
+                y: md.y as f32 + PAD_TOP,
+            };
+            draw_text_ex(header, font, pos, fs.size, fs.spacing, MOVES_FG_COLOUR);
+        }
+
+        let view = layout.view(md);
+        if let Some(footer) = &layout.footer {
+            let pos = Vector2 {
+                x: md.x as f32 + PAD_LEFT,
+                y: view.y + view.height + VSPACE,
+            };
+            draw_text_ex(footer, font, pos, fs.size, fs.spacing, MOVES_COMMENT_COLOUR);
+        }
+
+        for item in &layout.items {
+            let y = view.y + item.pos.y - self.scroll;
+            // Only lines wholly within the view are drawn
+            if y < view.y - 1.0 || y + fs.em.y > view.y + view.height + 1.0 {
+                continue;
+            }
+            if item.is_curr {
+                let rect = self.item_rect(layout, item, view);
+                let (x, y) = (rect.x.max(0.0) as u32, rect.y.max(0.0) as u32);
+                draw_rectangle(
+                    x,
+                    y,
+                    rect.width as u32,
+                    rect.height as u32,
+                    CURR_MOVE_BG_COLOUR,
+                );
+            }
+            let pos = Vector2 {
+                x: view.x + item.pos.x,
+                y,
+            };
+            draw_text_ex(&item.text, font, pos, fs.size, fs.spacing, item.colour);
+        }
+    }
+}
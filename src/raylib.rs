@@ -307,6 +307,7 @@ mod sys {
         pub fn CloseWindow();
         pub fn WindowShouldClose() -> bool;
         pub fn SetTargetFPS(fps: c_int);
+        pub fn GetFrameTime() -> c_float;
         pub fn GetRenderWidth() -> c_int;
         pub fn GetRenderHeight() -> c_int;
         pub fn SetWindowState(flags: c_uint);
@@ -345,6 +346,7 @@ mod sys {
         pub fn IsMouseButtonDown(button: c_int) -> c_int;
         pub fn IsMouseButtonReleased(button: c_int) -> c_int;
         pub fn IsMouseButtonPressed(button: c_int) -> c_int;
+        pub fn GetMouseWheelMove() -> c_float;
         pub fn SetExitKey(key: c_int);
         pub fn IsKeyReleased(key: c_int) -> c_int;
         pub fn GetCharPressed() -> c_int;
@@ -383,7 +385,6 @@ mod sys {
         pub fn UnloadSound(sound: Sound);
         pub fn PlaySound(sound: Sound);
 
-        pub fn GetFontDefault() -> Font;
         pub fn LoadFontFromMemory(
             file_type: *const c_char,
             file_data: *const c_uchar,
@@ -665,11 +666,6 @@ impl Font {
         };
         Font { font }
     }
-
-    pub fn load_default() -> Font {
-        let font = unsafe { sys::GetFontDefault() };
-        Font { font }
-    }
 }
 
 impl RenderTexture {
@@ -729,6 +725,14 @@ pub fn is_mouse_button_released(mb: MouseButton) -> bool {
     unsafe { sys::IsMouseButtonReleased(mb as _) != 0 }
 }
 
+pub fn get_mouse_wheel_move() -> f32 {
+    unsafe { sys::GetMouseWheelMove() }
+}
+
+pub fn get_frame_time() -> f32 {
+    unsafe { sys::GetFrameTime() }
+}
+
 pub fn is_key_released(key: Key) -> bool {
     unsafe { sys::IsKeyReleased(key as _) != 0 }
 }